@@ -1,20 +1,191 @@
 import { ReactNode, useEffect, useRef, useState } from "react";
-import { UpdateProvider } from "./components/UpdateContext";
 import { invoke } from "@tauri-apps/api/tauri";
-import{TreeNode, get_node} from "./components/TreeNode";
+import{TreeNode, get_node, reportError} from "./components/TreeNode";
 
 
 export const TreePage = () =>{
     const [rootNode, setRootNode] = useState<ReactNode | null>(null);
+    const [root, setRoot] = useState<{id: number, name: string} | null>(null);
+    const [roots, setRoots] = useState<{id: number, name: string}[]>([]);
+    const [query, setQuery] = useState("");
+    const [searchMode, setSearchMode] = useState("Fuzzy");
+    const [searchResults, setSearchResults] = useState<{hits: any[], truncated: boolean} | null>(null);
+    // 取值须与后端DiagramFormat的变体名一致
+    const [graphFormat, setGraphFormat] = useState("Mermaid");
     const fileInputRef = useRef<HTMLInputElement>(null);
     const handleButtonClick = () => {
       fileInputRef.current?.click();
     };
+    const loadRootNode = async () => {
+      const root_id = await invoke<number>("query_root");
+      const node = await invoke<any>("query_node", {id: root_id});
+      setRoot({id: root_id, name: node.name});
+      setRootNode(await get_node(root_id, null));
+    };
+    // 节点的内容由后端推送的事件刷新，这里只在根节点本身改变时（例如被合并到其他模型）重新加载
+    const refreshRootNode = async () => {
+      const root_id = await invoke<number>("query_root");
+      if (root_id !== root?.id) {
+        await loadRootNode();
+      }
+    };
+    const loadRoots = async () => {
+      const response = await invoke<any[]>("query_roots");
+      setRoots(response.map((node) => ({id: node.id, name: node.name})));
+    };
+    const selectRoot = async (id: number) => {
+      try {
+        await invoke("set_root", {id: id});
+      } catch (e) {
+        reportError(e);
+        return;
+      }
+      await loadRootNode();
+    };
+    const searchModels = async () => {
+      if (query.trim() === "") {
+        setSearchResults(null);
+        return;
+      }
+      try {
+        setSearchResults(await invoke<{hits: any[], truncated: boolean}>("search_models", {query: query, mode: searchMode}));
+      } catch (e) {
+        reportError(e);
+      }
+    };
+    const batchRename = async () => {
+      const pattern = prompt("要替换的名称（正则表达式）：");
+      if (!pattern) {
+        return;
+      }
+      const replacement = prompt("替换为（可以用$1等引用分组）：") ?? "";
+      try {
+        const preview = await invoke<any>("batch_rename", {pattern: pattern, replacement: replacement, dryRun: true});
+        if (preview.collisions.length > 0) {
+          alert("以下名称重命名后会重名，未做修改：\n" + preview.collisions.map((c: any) => c.names.join("、") + " → " + c.name).join("\n"));
+          return;
+        }
+        if (preview.renames.length === 0) {
+          alert("没有匹配的模型");
+          return;
+        }
+        const lines = preview.renames.slice(0, 20).map((r: any) => r.old_name + " → " + r.new_name);
+        if (preview.renames.length > 20) {
+          lines.push("……（共" + preview.renames.length + "个）");
+        }
+        if (!confirm("将重命名以下模型：\n" + lines.join("\n"))) {
+          return;
+        }
+        const report = await invoke<any>("batch_rename", {pattern: pattern, replacement: replacement, dryRun: false});
+        invoke("log", {message: "Next: 批量重命名了" + report.renames.length + "个模型"});
+      } catch (e) {
+        reportError(e);
+        return;
+      }
+      await loadRoots();
+    };
+    const exportGraph = async () => {
+      // 留空时不限层数
+      const depth = prompt("层数（留空不限）：") || null;
+      try {
+        const text = await invoke<string>("export_graph", {format: graphFormat, rootId: root?.id ?? null, depth: depth === null ? null : Number(depth)});
+        await navigator.clipboard.writeText(text);
+        alert("已复制到剪贴板");
+      } catch (e) {
+        reportError(e);
+      }
+    };
+    // .csv为边表，其他为大纲
+    const flatFormat = (filePath: string) => filePath.toLowerCase().endsWith(".csv") ? "Csv" : "Outline";
+    const exportFlat = async () => {
+      const filePath = prompt("导出到（.csv为边表，.txt为大纲）：");
+      if (!filePath) {
+        return;
+      }
+      try {
+        await invoke("export_flat", {format: flatFormat(filePath), filePath: filePath});
+        invoke("log", {message: "Next: 已导出到" + filePath});
+      } catch (e) {
+        reportError(e);
+      }
+    };
+    const importFlat = async () => {
+      const filePath = prompt("导入文件（.csv为边表，.txt为大纲）：");
+      if (!filePath) {
+        return;
+      }
+      try {
+        const preview = await invoke<any>("import_flat", {format: flatFormat(filePath), filePath: filePath, dryRun: true});
+        if (preview.added.length === 0 && preview.removed.length === 0 && preview.changed.length === 0) {
+          alert("导入的内容与当前模型相同");
+          return;
+        }
+        const lines = [
+          "新增" + preview.added.length + "个：" + preview.added.slice(0, 10).join("、"),
+          "删除" + preview.removed.length + "个：" + preview.removed.slice(0, 10).join("、"),
+          "修改" + preview.changed.length + "个：" + preview.changed.slice(0, 10).map((diff: any) => diff.name).join("、"),
+        ];
+        if (!confirm("导入将做以下修改，可以撤销：\n" + lines.join("\n"))) {
+          return;
+        }
+        await invoke("import_flat", {format: flatFormat(filePath), filePath: filePath, dryRun: false});
+      } catch (e) {
+        reportError(e);
+        return;
+      }
+      await refreshRootNode();
+      await loadRoots();
+    };
+    const undo = async () => {
+      const description = await invoke<string | null>("undo");
+      invoke("log", {message: "Next: 撤销：" + description});
+      await refreshRootNode();
+    };
+    const redo = async () => {
+      const description = await invoke<string | null>("redo");
+      invoke("log", {message: "Next: 重做：" + description});
+      await refreshRootNode();
+    };
+    const saveModels = async () => {
+      try {
+        await invoke("save_models");
+        invoke("log", {message: "Next: 模型已保存"});
+      } catch (e) {
+        reportError(e);
+      }
+    };
+    const recoverJournal = async () => {
+      const pending = await invoke<any[]>("query_pending_journal");
+      if (pending.length > 0) {
+        if (confirm("检测到上次未正常退出，是否恢复" + pending.length + "条未保存的修改？")) {
+          await invoke("replay_journal");
+        } else {
+          await invoke("discard_journal");
+        }
+      }
+    };
+    const openModelFile = async () => {
+      const filePath = prompt("模型文件路径：");
+      if (!filePath) {
+        return;
+      }
+      // 留空时自动推断根节点
+      const rootName = prompt("根节点（留空自动推断）：") || null;
+      try {
+        await invoke<number>("open_model_file", {filePath: filePath, rootName: rootName});
+        await recoverJournal();
+        await loadRootNode();
+        await loadRoots();
+      } catch (e) {
+        reportError(e);
+      }
+    };
     useEffect(() => {
         invoke("log", {message: "TreePage mounted"});
         const get_root_node = async () => {
-          let root_name = await invoke<string>("query_root_name");
-          setRootNode(await get_node(root_name, null));
+          await recoverJournal();
+          await loadRootNode();
+          await loadRoots();
         }
         get_root_node();
         handleButtonClick();
@@ -23,10 +194,49 @@ export const TreePage = () =>{
         <div className="p-4">
           
           <div className="inline-block">
-            <button className="mx-3 mb-2 px-4 py-2 bg-blue-600 text-white font-semibold rounded-2xl shadow-md hover:bg-blue-700 transition-all duration-200 ease-in-out active:scale-95">Save</button>
+            <button onClick={openModelFile} className="mx-3 mb-2 px-4 py-2 bg-blue-600 text-white font-semibold rounded-2xl shadow-md hover:bg-blue-700 transition-all duration-200 ease-in-out active:scale-95">Open</button>
+            <button onClick={saveModels} className="mx-3 mb-2 px-4 py-2 bg-blue-600 text-white font-semibold rounded-2xl shadow-md hover:bg-blue-700 transition-all duration-200 ease-in-out active:scale-95">Save</button>
+            <button onClick={batchRename} className="mx-3 mb-2 px-4 py-2 bg-blue-600 text-white font-semibold rounded-2xl shadow-md hover:bg-blue-700 transition-all duration-200 ease-in-out active:scale-95">Rename</button>
+            <button onClick={importFlat} className="mx-3 mb-2 px-4 py-2 bg-blue-600 text-white font-semibold rounded-2xl shadow-md hover:bg-blue-700 transition-all duration-200 ease-in-out active:scale-95">Import</button>
+            <button onClick={exportFlat} className="mx-3 mb-2 px-4 py-2 bg-blue-600 text-white font-semibold rounded-2xl shadow-md hover:bg-blue-700 transition-all duration-200 ease-in-out active:scale-95">Export</button>
+            <button onClick={exportGraph} className="ml-3 mb-2 px-4 py-2 bg-blue-600 text-white font-semibold rounded-2xl shadow-md hover:bg-blue-700 transition-all duration-200 ease-in-out active:scale-95">Diagram</button>
+            <select value={graphFormat} onChange={(e) => setGraphFormat(e.target.value)} className="border px-1 ml-1 mr-3 rounded">
+              <option value="Mermaid">Mermaid</option>
+              <option value="Dot">Dot</option>
+            </select>
+            <button onClick={undo} className="mx-3 mb-2 px-4 py-2 bg-blue-600 text-white font-semibold rounded-2xl shadow-md hover:bg-blue-700 transition-all duration-200 ease-in-out active:scale-95">Undo</button>
+            <button onClick={redo} className="mx-3 mb-2 px-4 py-2 bg-blue-600 text-white font-semibold rounded-2xl shadow-md hover:bg-blue-700 transition-all duration-200 ease-in-out active:scale-95">Redo</button>
             <button className="mx-3 px-4 py-2 bg-blue-600 text-white font-semibold rounded-2xl shadow-md hover:bg-blue-700 transition-all duration-200 ease-in-out active:scale-95">Back</button>
           </div>
           <h1 className="text-xl font-bold mb-4">文件：</h1>
+          <div className="mb-4">
+            入口：
+            <select value={root?.id ?? ""} onFocus={loadRoots} onChange={(e) => selectRoot(Number(e.target.value))} className="border px-1 rounded">
+              {(root === null || roots.some((node) => node.id === root.id) ? roots : [root, ...roots]).map((node) => <option key={node.id} value={node.id}>{node.name}</option>)}
+            </select>
+          </div>
+          <div className="mb-4">
+            搜索：
+            <input value={query} onChange={(e) => setQuery(e.target.value)} onKeyDown={(e) => { if (e.key === "Enter") searchModels(); }} placeholder="名称、拼音或首字母" className="border px-1 rounded" />
+            <select value={searchMode} onChange={(e) => setSearchMode(e.target.value)} className="border px-1 mx-2 rounded">
+              <option value="Fuzzy">模糊</option>
+              <option value="Substring">子串</option>
+              <option value="Regex">正则</option>
+            </select>
+            <button onClick={searchModels} className="px-2 border rounded">搜索</button>
+            {searchResults !== null && (
+              <ul className="mt-2">
+                {searchResults.hits.length === 0 && <li>没有匹配的模型</li>}
+                {searchResults.hits.map((hit) => (
+                  <li key={hit.id}>
+                    <span className="font-semibold">{hit.name}</span>
+                    <span className="text-gray-500 ml-2">{hit.path.names.join(" / ")}{hit.reachable ? "" : "（无法从当前入口到达）"}</span>
+                  </li>
+                ))}
+                {searchResults.truncated && <li>只显示了前{searchResults.hits.length}个结果</li>}
+              </ul>
+            )}
+          </div>
           {rootNode}
         </div>
       );
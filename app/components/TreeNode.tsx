@@ -4,10 +4,25 @@ import { ReactNode, useEffect, useState } from "react";
 import { invoke } from '@tauri-apps/api/tauri'
 import { motion } from "framer-motion";
 import { ChevronRight, ChevronDown, Folder, FileText, Plus, Trash2, CircuitBoard, Dot } from "lucide-react";
-import { useTriggerUpdate, useUpdateListener } from "./UpdateContext";
+import { listen } from "@tauri-apps/api/event";
 
 let index = 0;
 
+// 复制或剪切的节点，粘贴时共享、复制副本或移动
+let clipboard: {id: number, parent: number | null, cut: boolean} | null = null;
+
+// 后端命令返回的错误，见src-tauri/src/error.rs
+interface ModelError {
+  kind: string;
+  message: string;
+}
+
+export const reportError = (e: unknown) => {
+  const message = (e as ModelError)?.message ?? String(e);
+  invoke("log", {message: "Next: 错误：" + message});
+  alert(message);
+}
+
 function generateIndex() {
   return index++;
 }
@@ -15,93 +30,129 @@ function generateIndex() {
 
 interface TreeNodeProps {
   id: number;
+  // 后端模型的ID，id只用于React的key
+  modelId: number;
   name: string;
   hasChildren: boolean;
-  parent: string | null;
+  parent: number | null;
+  // 在父节点children中的位置
+  position: number;
+  pinned: boolean;
   // refreshTree: () => void; // Function to re-fetch data from backend
 }
 
-export const get_node = async (name: string, parent: string|null): Promise<ReactNode> => {
-  await invoke("log", {message: "getting node: " + name});
-  return invoke<any>("query_node", { name: name }).then((node) => {
+export const get_node = async (modelId: number, parent: number|null, position: number = 0): Promise<ReactNode> => {
+  await invoke("log", {message: "getting node: " + modelId});
+  return invoke<any>("query_node", { id: modelId }).then((node) => {
     const id = generateIndex();
     // invoke("log", {message: "generated id: " + id});
-    return <TreeNode key={id} id={id} name={node.name} hasChildren={node.has_children} parent={parent}/>;
+    return <TreeNode key={id} id={id} modelId={node.id} name={node.name} hasChildren={node.has_children} parent={parent} position={position} pinned={node.pinned}/>;
   });
 }
 
-export const TreeNode = ({ id, name, hasChildren, parent }: TreeNodeProps) => {
+export const TreeNode = ({ id, modelId, name, hasChildren, parent, position, pinned }: TreeNodeProps) => {
   invoke("log", {message: "Next: 生成新的节点：" + name});
   const [children, setChildren] = useState<ReactNode[]>([]);
-  const [childrenNames, setChildrenNames] = useState<string[]>([]);
   const [algorithm, setAlgorithm] = useState("加载中");
   const [expanded, setExpanded] = useState(false);
-  const [prevExpanded, setPrevExpanded] = useState(expanded);
   const [editing, setEditing] = useState(false);
   const [algoEditing, setAlgoEditing] = useState(false);
   const [prevName, setPrevName] = useState(name);
   const [newName, setNewName] = useState(name);
   const [refCount, setRefCount] = useState(0);
+  const [isPinned, setIsPinned] = useState(pinned);
+  const [nodeHasChildren, setNodeHasChildren] = useState(hasChildren);
+  // 收到children-changed事件时加一，使展开的节点重新获取子节点
+  const [childrenVersion, setChildrenVersion] = useState(0);
+  // 重命名时合并到同名模型后，节点对应的模型随之改变
+  const [nodeId, setNodeId] = useState(modelId);
 
-  const triggerUpdate = useTriggerUpdate();
-  const context = useUpdateListener();
-
-  useEffect(() => {
-    invoke("log", {message: "useEffect 被调用，名字：" + newName});
-    const fetchChildren = async () => {
-      // await invoke("log", {message: "querying children for " + newName});
-      const response = await invoke<string[]>("query_children", { parentName: newName });
-      const get_children = async () =>{
-        let children: ReactNode[] = [];
-        for (let i = 0; i < response.length; i++){
-          children.push(await get_node(response[i], newName));
-        }
-        return children;
-      }
-      invoke("log", {message: "children names of " + newName + ": " + response});
-      setChildren(await get_children());
-      setChildrenNames(response);
-      return ()=>{
-        invoke("log", {message: "Next: 删除节点：" + newName});
-      }
-    }
-    const fetchAlgorithm = async () =>{
-      const response = await invoke<string>("query_algorithm", { parentName: newName });
-      setAlgorithm(response);
-    }
-    const fetchRefCount = async () =>{
-      const response = await invoke<number>("query_ref_count", { name: newName });
-      setRefCount(response);
+  const fetchChildren = async () => {
+    const response = await invoke<number[]>("query_children", { parentId: nodeId });
+    let children: ReactNode[] = [];
+    for (let i = 0; i < response.length; i++){
+      children.push(await get_node(response[i], nodeId, i));
     }
-    // invoke("log", {message: "children names of " + newName + ": " + childrenNames});
-    invoke("log", {message: "prevModifiedName: " + context.prevModifiedName});
-    fetchRefCount();
+    invoke("log", {message: "children names of " + newName + ": " + response});
+    setChildren(children);
+  }
+  const fetchAlgorithm = async () =>{
+    const response = await invoke<string>("query_algorithm", { id: nodeId });
+    setAlgorithm(response);
+  }
 
+  useEffect(() => {
+    // 节点可能在刚被删除后仍在渲染，未找到模型时只记录日志
+    invoke<number>("query_ref_count", { id: nodeId }).then(setRefCount).catch((e) => invoke("log", {message: "Next: " + ((e as ModelError)?.message ?? String(e))}));
+  }, [nodeId]);
 
-    // fetchRefCount();
-    if (expanded && (!prevExpanded || newName == context.addedNodeParent || childrenNames.includes(context.prevModifiedName))){
-      // assert(hasChildren);
+  // 展开时以及子节点改变后重新获取子节点和算法
+  useEffect(() => {
+    if (expanded && nodeHasChildren) {
       invoke("log", {message: "fetching children for " + newName});
       fetchChildren();
       fetchAlgorithm();
     }
-    else if (!expanded){
+  }, [expanded, nodeHasChildren, childrenVersion, nodeId]);
 
-    }
-    setPrevExpanded(expanded);
-  }, [expanded, context]);
+  // 后端修改模型后推送事件，每个节点只处理与自己对应的模型，见src-tauri/src/events.rs
+  useEffect(() => {
+    const find = <T extends {id: number}>(models: T[]) => models.find((model) => model.id === nodeId);
+    const unlisteners = [
+      listen<{models: {id: number, name: string}[]}>("node-renamed", (event) => {
+        const model = find(event.payload.models);
+        if (model) {
+          setNewName(model.name);
+          setPrevName(model.name);
+        }
+      }),
+      listen<{models: {id: number, children: number[] | null}[]}>("children-changed", (event) => {
+        const model = find(event.payload.models);
+        if (model) {
+          setNodeHasChildren(model.children !== null);
+          setChildrenVersion((version) => version + 1);
+        }
+      }),
+      listen<{models: {id: number, ref_count: number}[]}>("ref-count-changed", (event) => {
+        const model = find(event.payload.models);
+        if (model) {
+          setRefCount(model.ref_count);
+        }
+      }),
+      listen<{models: {id: number, algorithm: string | null}[]}>("algorithm-changed", (event) => {
+        const model = find(event.payload.models);
+        if (model) {
+          setAlgorithm(model.algorithm ?? "");
+        }
+      }),
+      listen<{models: {id: number, pinned: boolean}[]}>("pinned-changed", (event) => {
+        const model = find(event.payload.models);
+        if (model) {
+          setIsPinned(model.pinned);
+        }
+      }),
+    ];
+    return () => {
+      unlisteners.forEach((unlisten) => unlisten.then((f) => f()));
+    };
+  }, [nodeId]);
 
   // 🔄 Update Node Name
   const updateNodeName = async () => {
     invoke("log", {message: "prevName: " + prevName + " newName: " + newName});
     if (newName.trim() !== prevName.trim()) {
-      let response = await invoke<any>("update_node_name", {name: prevName, newName: newName });
+      let response;
+      try {
+        response = await invoke<any>("update_node_name", {id: nodeId, newName: newName });
+      } catch (e) {
+        reportError(e);
+        setNewName(prevName);
+        setEditing(false);
+        return;
+      }
       invoke("log", {message: "Next: " + prevName + " renamed to " + response.new_name});
       setNewName(response.new_name);
-      if (response.requires_update){
-        invoke("log", {message: "----------更新被触发了：" + prevName});
-        triggerUpdate(prevName, "");
-      }
+      setNodeId(response.id);
       // the response is composed of two parts: the actual modified name and the actions to be taken ...?
 
       // 
@@ -121,27 +172,173 @@ export const TreeNode = ({ id, name, hasChildren, parent }: TreeNodeProps) => {
 
   // ➕ Add New Item
   const addNewItem = async () => {
-    const newChildName = await invoke("add_node", { parentName: newName });
-    invoke("log", {message: "Next: 尝试在"+newName +"中添加新的节点：" + newChildName});
-    invoke("log", {message: "----------更新因为Add被触发了：" + prevName});
-    triggerUpdate("", newName);
+    let newChildId;
+    try {
+      newChildId = await invoke("add_node", { parentId: nodeId });
+    } catch (e) {
+      reportError(e);
+      return;
+    }
+    invoke("log", {message: "Next: 尝试在"+newName +"中添加新的节点：" + newChildId});
     // refreshTree();
     // setExpanded(true);
   };
   const toggleHasChildren = async () => {
-    invoke("toggle_has_children", { name: newName });
-    triggerUpdate(newName, "");
+    try {
+      // 改为叶节点时不再被引用的子孙模型会被一并删除，删除前确认
+      const preview = await invoke<any>("preview_toggle_has_children", { id: nodeId });
+      if (preview.removed.length > 0 && !confirm("将同时删除以下" + preview.removed.length + "个模型：\n" + preview.removed.map((node: any) => node.name).join("\n"))) {
+        return;
+      }
+      await invoke("toggle_has_children", { id: nodeId });
+    } catch (e) {
+      reportError(e);
+      return;
+    }
   }
   const updateAlgorithm = async() => {
-    invoke("update_algorithm", { name: newName, algorithm: algorithm });
-    triggerUpdate("", newName);
+    try {
+      await invoke("update_algorithm", { id: nodeId, algorithm: algorithm });
+    } catch (e) {
+      reportError(e);
+      // 恢复显示修改前的算法
+      fetchAlgorithm();
+    }
     setAlgoEditing(false);
   }
 
+  // 设为入口后可以在根节点列表中选择
+  const togglePinned = async () => {
+    try {
+      await invoke(isPinned ? "remove_root" : "add_root", { id: nodeId });
+    } catch (e) {
+      reportError(e);
+    }
+  }
+
+  // 在父节点中上移或下移一位
+  const moveNode = async (offset: number) => {
+    if (position + offset < 0) {
+      return;
+    }
+    try {
+      await invoke("move_child", { parentId: parent, id: nodeId, newIndex: position + offset });
+    } catch (e) {
+      reportError(e);
+      return;
+    }
+  }
+
+  // 在这个节点之前插入新的节点
+  const insertBefore = async () => {
+    try {
+      await invoke("insert_node_at", { parentId: parent, index: position });
+    } catch (e) {
+      reportError(e);
+      return;
+    }
+  }
+
+  const copyNode = (cut: boolean) => {
+    clipboard = {id: nodeId, parent: parent, cut: cut};
+    invoke("log", {message: "Next: " + (cut ? "剪切" : "复制") + "节点：" + newName});
+  }
+
+  // 复制的节点粘贴为共享的引用，剪切的节点移动到这里
+  const paste = async () => {
+    if (clipboard === null) {
+      alert("请先复制或剪切一个节点");
+      return;
+    }
+    try {
+      if (clipboard.cut) {
+        if (clipboard.parent === null) {
+          alert("不能移动根节点");
+          return;
+        }
+        await invoke("move_node", { oldParentId: clipboard.parent, newParentId: nodeId, id: clipboard.id });
+        clipboard = null;
+      } else {
+        await invoke("link_existing_node", { parentId: nodeId, id: clipboard.id });
+      }
+    } catch (e) {
+      reportError(e);
+    }
+  }
+
+  const pasteCopy = async () => {
+    if (clipboard === null) {
+      alert("请先复制或剪切一个节点");
+      return;
+    }
+    try {
+      await invoke("deep_copy_subtree", { id: clipboard.id, newParentId: nodeId });
+    } catch (e) {
+      reportError(e);
+      return;
+    }
+  }
+
+  // 将复制的节点合并到这个节点，所有引用它的地方改为引用这个节点
+  const mergeInto = async () => {
+    if (clipboard === null || clipboard.id === nodeId) {
+      alert("请先复制要合并到这里的另一个节点");
+      return;
+    }
+    const sourceId = clipboard.id;
+    const source = await invoke<any>("query_node", { id: sourceId });
+    if (!confirm("将" + source.name + "合并到" + newName + "？")) {
+      return;
+    }
+    // 取消时以被合并节点的子节点和算法替换这个节点的
+    const strategy = confirm("保留双方的子节点？选择取消将改用" + source.name + "的子节点和算法") ? "Union" : "Replace";
+    let report;
+    try {
+      report = await invoke<any>("merge_nodes", { sourceId: sourceId, targetId: nodeId, strategy: strategy });
+    } catch (e) {
+      reportError(e);
+      return;
+    }
+    clipboard = null;
+    const lines = ["已将" + report.source.name + "合并到" + report.target.name];
+    if (report.added_children.length > 0) {
+      lines.push("加入的子节点：" + report.added_children.map((node: any) => node.name).join("、"));
+    }
+    if (report.removed.length > 0) {
+      lines.push("删除了不再被引用的模型：" + report.removed.map((node: any) => node.name).join("、"));
+    }
+    if (report.discarded_algorithm !== null) {
+      lines.push("未采用的算法：" + report.discarded_algorithm);
+    }
+    alert(lines.join("\n"));
+  }
+
+  // 共享的模型被修改时所有引用它的地方都会受影响，修改前可以查看所有路径
+  const showUsagePaths = async () => {
+    let response;
+    try {
+      response = await invoke<any>("query_usage_paths", { id: nodeId });
+    } catch (e) {
+      reportError(e);
+      return;
+    }
+    const lines = response.paths.map((path: any) => path.names.join(" / "));
+    alert("被使用的位置：\n" + lines.join("\n") + (response.truncated ? "\n……（路径过多，只显示一部分）" : ""));
+  }
+
   // 🗑 Delete Node
   const deleteNode = async () => {
-    await invoke("delete_node", { parentName: parent, name: newName });
-    triggerUpdate(newName, "");
+    try {
+      // 不再被引用的子孙模型会被一并删除，删除前确认
+      const preview = await invoke<any>("preview_delete_node", { parentId: parent, id: nodeId });
+      if (preview.removed.length > 1 && !confirm("将同时删除以下" + preview.removed.length + "个模型：\n" + preview.removed.map((node: any) => node.name).join("\n"))) {
+        return;
+      }
+      await invoke("delete_node", { parentId: parent, id: nodeId });
+    } catch (e) {
+      reportError(e);
+      return;
+    }
     // refreshTree();
   };
 
@@ -150,7 +347,7 @@ export const TreeNode = ({ id, name, hasChildren, parent }: TreeNodeProps) => {
       {/* Node Header */}
       <div className="flex items-center gap-2 cursor-pointer hover:bg-gray-100 p-1 rounded-md">
         {/* Expand/Collapse Button for Parent Nodes */}
-        {hasChildren ? (
+        {nodeHasChildren ? (
           <div onClick={() => setExpanded(!expanded)}>
             {expanded ? <ChevronDown size={16} /> : <ChevronRight size={16} />}
           </div>
@@ -159,7 +356,7 @@ export const TreeNode = ({ id, name, hasChildren, parent }: TreeNodeProps) => {
         )}
 
         {/* Folder or File Icon */}
-        {hasChildren ? <Folder size={16} className="text-yellow-500" /> : <FileText size={16} className="text-yellow-500" />}
+        {nodeHasChildren ? <Folder size={16} className="text-yellow-500" /> : <FileText size={16} className="text-yellow-500" />}
 
         {/* Editable Name */}
         {editing ? (
@@ -180,9 +377,32 @@ export const TreeNode = ({ id, name, hasChildren, parent }: TreeNodeProps) => {
         {
           <div className="ml-auto">
             <button className="text-blue-500 hover:text-blue-700 mr-3" onClick={toggleHasChildren} >
-              {hasChildren ? "删除子项" : "启用子项"}
+              {nodeHasChildren ? "删除子项" : "启用子项"}
+            </button>
+            {parent !== null && <>
+              <button className="text-blue-500 hover:text-blue-700 mr-3" onClick={() => moveNode(-1)} >
+                上移
+              </button>
+              <button className="text-blue-500 hover:text-blue-700 mr-3" onClick={() => moveNode(1)} >
+                下移
+              </button>
+              <button className="text-blue-500 hover:text-blue-700 mr-3" onClick={insertBefore} >
+                插入
+              </button>
+            </>}
+            <button className="text-blue-500 hover:text-blue-700 mr-3" onClick={() => copyNode(false)} >
+              复制
+            </button>
+            <button className="text-blue-500 hover:text-blue-700 mr-3" onClick={() => copyNode(true)} >
+              剪切
             </button>
-            <div className="inline-block mr-3">引用计数：{refCount}</div>
+            <button className="text-blue-500 hover:text-blue-700 mr-3" onClick={mergeInto} >
+              合并到此
+            </button>
+            <button className="text-blue-500 hover:text-blue-700 mr-3" onClick={togglePinned} >
+              {isPinned ? "取消入口" : "设为入口"}
+            </button>
+            <div className="inline-block mr-3" onClick={showUsagePaths}>引用计数：{refCount}</div>
             <button className="text-red-500 hover:text-red-700" onClick={deleteNode} >
               <Trash2 size={16} />
             </button>
@@ -192,7 +412,7 @@ export const TreeNode = ({ id, name, hasChildren, parent }: TreeNodeProps) => {
       </div>
 
       {/* Children Nodes (if expanded) */}
-      {hasChildren && expanded && (
+      {nodeHasChildren && expanded && (
         <motion.div
           initial={{ opacity: 0, height: 0 }}
           animate={{ opacity: 1, height: "auto" }}
@@ -227,6 +447,12 @@ export const TreeNode = ({ id, name, hasChildren, parent }: TreeNodeProps) => {
             <button onClick={addNewItem} className="text-blue-500 hover:text-blue-700">
               添加
             </button>
+            <button onClick={paste} className="text-blue-500 hover:text-blue-700">
+              粘贴
+            </button>
+            <button onClick={pasteCopy} className="text-blue-500 hover:text-blue-700">
+              粘贴副本
+            </button>
           </div>
         </motion.div>
       )}
@@ -2,7 +2,6 @@
 
 import { ReactNode, useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/tauri";
-import { UpdateProvider } from "./components/UpdateContext";
 import { TreePage } from "./TreePage";
 
 
@@ -13,8 +12,8 @@ export default function Page() {
     setPage(<TreePage />);
   }, []);
   return (
-    <UpdateProvider>
+    <>
       {page}
-    </UpdateProvider>
+    </>
   );
 }
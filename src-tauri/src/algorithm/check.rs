@@ -0,0 +1,205 @@
+use super::parser::{Arg, BinaryOp, Expr, UnaryOp};
+use super::{AlgorithmError, Function};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Number,
+    Bool,
+}
+
+impl Type {
+    fn describe(self) -> &'static str {
+        match self {
+            Type::Number => "数值",
+            Type::Bool => "条件",
+        }
+    }
+}
+
+// 检查表达式的类型，并确认所有引用的名称都是子节点
+pub fn check(expr: &Expr, children: &[String]) -> Result<Type, AlgorithmError> {
+    match expr {
+        Expr::Number(_) => Ok(Type::Number),
+        Expr::Ref { name, position } => {
+            if children.iter().any(|child| child == name) {
+                Ok(Type::Number)
+            } else {
+                Err(AlgorithmError::at(*position, format!("{}不是子节点", name)))
+            }
+        }
+        Expr::Unary { op, operand, position } => {
+            let expected = match op {
+                UnaryOp::Neg => Type::Number,
+                UnaryOp::Not => Type::Bool,
+            };
+            expect(operand, expected, children, *position)?;
+            Ok(expected)
+        }
+        Expr::Binary { op, left, right, position } => {
+            let (operand, result) = match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => (Type::Number, Type::Number),
+                BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge | BinaryOp::Eq | BinaryOp::Ne => (Type::Number, Type::Bool),
+                BinaryOp::And | BinaryOp::Or => (Type::Bool, Type::Bool),
+            };
+            expect(left, operand, children, *position)?;
+            expect(right, operand, children, *position)?;
+            Ok(result)
+        }
+        Expr::Call { function, args, position } => check_call(function, args, children, *position),
+    }
+}
+
+fn expect(expr: &Expr, expected: Type, children: &[String], position: usize) -> Result<(), AlgorithmError> {
+    let actual = check(expr, children)?;
+    if actual != expected {
+        return Err(AlgorithmError::at(position, format!("此处应为{}，实际为{}", expected.describe(), actual.describe())));
+    }
+    Ok(())
+}
+
+fn expect_single<'a>(arg: &'a Arg, function: &str, position: usize) -> Result<&'a Expr, AlgorithmError> {
+    match arg {
+        Arg::Single(expr) => Ok(expr),
+        Arg::Pair(..) => Err(AlgorithmError::at(position, format!("{}的参数不能使用“:”", function))),
+    }
+}
+
+fn expect_pair<'a>(arg: &'a Arg, function: &str, position: usize) -> Result<(&'a Expr, &'a Expr), AlgorithmError> {
+    match arg {
+        Arg::Pair(first, second) => Ok((first, second)),
+        Arg::Single(_) => Err(AlgorithmError::at(position, format!("{}的参数应为“a: b”的形式", function))),
+    }
+}
+
+fn check_call(function: &str, args: &[Arg], children: &[String], position: usize) -> Result<Type, AlgorithmError> {
+    let parsed = Function::lookup(function)
+        .ok_or_else(|| AlgorithmError::at(position, format!("未知的函数{}", function)))?;
+    let arity_error = |expected: &str| AlgorithmError::at(position, format!("{}需要{}个参数，实际为{}个", function, expected, args.len()));
+    match parsed {
+        Function::Sum | Function::Mean | Function::Min | Function::Max => {
+            if args.is_empty() {
+                return Err(arity_error("至少1"));
+            }
+            for arg in args {
+                expect(expect_single(arg, function, position)?, Type::Number, children, position)?;
+            }
+            Ok(Type::Number)
+        }
+        Function::WeightedSum | Function::WeightedMean => {
+            if args.is_empty() {
+                return Err(arity_error("至少1"));
+            }
+            for arg in args {
+                let (value, weight) = expect_pair(arg, function, position)?;
+                expect(value, Type::Number, children, position)?;
+                expect(weight, Type::Number, children, position)?;
+            }
+            Ok(Type::Number)
+        }
+        Function::Threshold => {
+            if args.len() != 2 && args.len() != 4 {
+                return Err(arity_error("2或4"));
+            }
+            for arg in args {
+                expect(expect_single(arg, function, position)?, Type::Number, children, position)?;
+            }
+            Ok(Type::Number)
+        }
+        Function::Clamp | Function::Abs => {
+            let expected = if parsed == Function::Clamp { 3 } else { 1 };
+            if args.len() != expected {
+                return Err(arity_error(&expected.to_string()));
+            }
+            for arg in args {
+                expect(expect_single(arg, function, position)?, Type::Number, children, position)?;
+            }
+            Ok(Type::Number)
+        }
+        Function::If => {
+            if args.len() != 3 {
+                return Err(arity_error("3"));
+            }
+            expect(expect_single(&args[0], function, position)?, Type::Bool, children, position)?;
+            let result = check(expect_single(&args[1], function, position)?, children)?;
+            expect(expect_single(&args[2], function, position)?, result, children, position)?;
+            Ok(result)
+        }
+        Function::Piecewise => {
+            // 除最后一个默认值外，每个参数都是“条件: 值”
+            let (default, cases) = match args.split_last() {
+                Some((default, cases)) if !cases.is_empty() => (default, cases),
+                _ => return Err(arity_error("至少2")),
+            };
+            for case in cases {
+                let (condition, value) = expect_pair(case, function, position)?;
+                expect(condition, Type::Bool, children, position)?;
+                expect(value, Type::Number, children, position)?;
+            }
+            expect(expect_single(default, function, position)?, Type::Number, children, position)?;
+            Ok(Type::Number)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse;
+    use super::*;
+
+    fn checked(source: &str) -> Result<Type, AlgorithmError> {
+        let children = ["a", "b", "c", "全身 (组织)"].map(String::from);
+        check(&parse(source).unwrap(), &children)
+    }
+
+    // (出错的字符位置, 消息)
+    fn error(source: &str) -> (Option<usize>, String) {
+        let error = checked(source).unwrap_err();
+        (error.column, error.message)
+    }
+
+    #[test]
+    fn result_types() {
+        assert_eq!(checked("a + b * 2"), Ok(Type::Number));
+        assert_eq!(checked("a > b and not c == 1"), Ok(Type::Bool));
+        assert_eq!(checked("if(a > 1, b > 1, c > 1)"), Ok(Type::Bool));
+        assert_eq!(checked("\"全身 (组织)\" / 2"), Ok(Type::Number));
+    }
+
+    #[test]
+    fn unknown_reference_reports_its_position() {
+        assert_eq!(error("a + d"), (Some(4), "d不是子节点".to_string()));
+        assert_eq!(error("sum(a, 全身)").0, Some(7));
+    }
+
+    #[test]
+    fn operand_type_errors_report_operator_position() {
+        assert_eq!(error("a + (b > 1)"), (Some(2), "此处应为数值，实际为条件".to_string()));
+        assert_eq!(error("a and b > 1"), (Some(2), "此处应为条件，实际为数值".to_string()));
+        assert_eq!(error("not a").0, Some(0));
+        assert_eq!(error("-(a > 1)").0, Some(0));
+    }
+
+    #[test]
+    fn function_arguments() {
+        assert_eq!(error("foo(a)"), (Some(0), "未知的函数foo".to_string()));
+        assert_eq!(error("a + sum()"), (Some(4), "sum需要至少1个参数，实际为0个".to_string()));
+        assert_eq!(error("threshold(a, 1, 2)").1, "threshold需要2或4个参数，实际为3个");
+        assert_eq!(error("clamp(a, 0)").1, "clamp需要3个参数，实际为2个");
+        assert!(error("weighted_sum(a: 1, b)").1.contains("“a: b”"));
+        assert!(error("mean(a: 1)").1.contains("不能使用“:”"));
+        assert_eq!(checked("threshold(a, 1, 10, 20) + abs(b)"), Ok(Type::Number));
+    }
+
+    #[test]
+    fn if_and_piecewise() {
+        assert_eq!(error("if(a, b, c)").1, "此处应为条件，实际为数值");
+        // 两个分支的类型须一致
+        assert_eq!(error("if(a > 1, b, c > 1)").1, "此处应为数值，实际为条件");
+        assert_eq!(error("if(a > 1, b)").1, "if需要3个参数，实际为2个");
+        assert_eq!(checked("piecewise(a < 90: 0, a < 140: 100, 60)"), Ok(Type::Number));
+        assert_eq!(error("piecewise(60)").1, "piecewise需要至少2个参数，实际为1个");
+        assert!(error("piecewise(a < 90, b)").1.contains("“a: b”"));
+        assert_eq!(error("piecewise(a: 0, 60)").1, "此处应为条件，实际为数值");
+        assert_eq!(error("piecewise(a < 90: 0, b < 1)").1, "此处应为数值，实际为条件");
+    }
+}
@@ -0,0 +1,236 @@
+use super::parser::{Arg, BinaryOp, Expr, UnaryOp};
+use super::{AlgorithmError, Function};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+}
+
+// 计算已通过类型检查的表达式，lookup返回子节点的值；未经检查的表达式类型或参数个数有误时返回错误
+pub fn evaluate(expr: &Expr, lookup: &dyn Fn(&str) -> Option<f64>) -> Result<f64, AlgorithmError> {
+    number(expr, lookup)
+}
+
+fn number(expr: &Expr, lookup: &dyn Fn(&str) -> Option<f64>) -> Result<f64, AlgorithmError> {
+    match value(expr, lookup)? {
+        Value::Number(number) => Ok(number),
+        Value::Bool(_) => Err(AlgorithmError::new("此处应为数值，实际为条件".to_string())),
+    }
+}
+
+fn condition(expr: &Expr, lookup: &dyn Fn(&str) -> Option<f64>) -> Result<bool, AlgorithmError> {
+    match value(expr, lookup)? {
+        Value::Bool(condition) => Ok(condition),
+        Value::Number(_) => Err(AlgorithmError::new("此处应为条件，实际为数值".to_string())),
+    }
+}
+
+fn value(expr: &Expr, lookup: &dyn Fn(&str) -> Option<f64>) -> Result<Value, AlgorithmError> {
+    match expr {
+        Expr::Number(number) => Ok(Value::Number(*number)),
+        Expr::Ref { name, position } => lookup(name)
+            .map(Value::Number)
+            .ok_or_else(|| AlgorithmError::at(*position, format!("缺少{}的值", name))),
+        Expr::Unary { op: UnaryOp::Neg, operand, .. } => Ok(Value::Number(-number(operand, lookup)?)),
+        Expr::Unary { op: UnaryOp::Not, operand, .. } => Ok(Value::Bool(!condition(operand, lookup)?)),
+        Expr::Binary { op: BinaryOp::And, left, right, .. } => {
+            Ok(Value::Bool(condition(left, lookup)? && condition(right, lookup)?))
+        }
+        Expr::Binary { op: BinaryOp::Or, left, right, .. } => {
+            Ok(Value::Bool(condition(left, lookup)? || condition(right, lookup)?))
+        }
+        Expr::Binary { op, left, right, position } => {
+            let left = number(left, lookup)?;
+            let right = number(right, lookup)?;
+            Ok(match op {
+                BinaryOp::Add => Value::Number(left + right),
+                BinaryOp::Sub => Value::Number(left - right),
+                BinaryOp::Mul => Value::Number(left * right),
+                BinaryOp::Div => {
+                    if right == 0.0 {
+                        return Err(AlgorithmError::at(*position, "除数为0".to_string()));
+                    }
+                    Value::Number(left / right)
+                }
+                BinaryOp::Lt => Value::Bool(left < right),
+                BinaryOp::Le => Value::Bool(left <= right),
+                BinaryOp::Gt => Value::Bool(left > right),
+                BinaryOp::Ge => Value::Bool(left >= right),
+                BinaryOp::Eq => Value::Bool(left == right),
+                BinaryOp::Ne => Value::Bool(left != right),
+                BinaryOp::And | BinaryOp::Or => unreachable!(),
+            })
+        }
+        Expr::Call { function, args, position } => call(function, args, *position, lookup),
+    }
+}
+
+fn single(arg: &Arg) -> &Expr {
+    match arg {
+        Arg::Single(expr) | Arg::Pair(expr, _) => expr,
+    }
+}
+
+fn numbers(args: &[Arg], lookup: &dyn Fn(&str) -> Option<f64>) -> Result<Vec<f64>, AlgorithmError> {
+    args.iter().map(|arg| number(single(arg), lookup)).collect()
+}
+
+// 加权参数的(值, 权重)
+fn weighted(args: &[Arg], lookup: &dyn Fn(&str) -> Option<f64>) -> Result<Vec<(f64, f64)>, AlgorithmError> {
+    args.iter().map(|arg| match arg {
+        Arg::Pair(value, weight) => Ok((number(value, lookup)?, number(weight, lookup)?)),
+        Arg::Single(value) => Ok((number(value, lookup)?, 1.0)),
+    }).collect()
+}
+
+fn call(function: &str, args: &[Arg], position: usize, lookup: &dyn Fn(&str) -> Option<f64>) -> Result<Value, AlgorithmError> {
+    let parsed = Function::lookup(function)
+        .ok_or_else(|| AlgorithmError::at(position, format!("未知的函数{}", function)))?;
+    // 参数个数通常已在类型检查中确认，这里只防止按位置取参数时越界
+    let require = |count: usize| match args.len() < count {
+        true => Err(AlgorithmError::at(position, format!("{}需要至少{}个参数，实际为{}个", function, count, args.len()))),
+        false => Ok(()),
+    };
+    let result = match parsed {
+        Function::Sum => numbers(args, lookup)?.iter().sum(),
+        Function::Mean => {
+            let values = numbers(args, lookup)?;
+            values.iter().sum::<f64>() / values.len() as f64
+        }
+        Function::Min => numbers(args, lookup)?.into_iter().fold(f64::INFINITY, f64::min),
+        Function::Max => numbers(args, lookup)?.into_iter().fold(f64::NEG_INFINITY, f64::max),
+        Function::WeightedSum => weighted(args, lookup)?.iter().map(|(value, weight)| value * weight).sum(),
+        Function::WeightedMean => {
+            let values = weighted(args, lookup)?;
+            let total_weight = values.iter().map(|(_, weight)| weight).sum::<f64>();
+            if total_weight == 0.0 {
+                return Err(AlgorithmError::at(position, "权重之和为0".to_string()));
+            }
+            values.iter().map(|(value, weight)| value * weight).sum::<f64>() / total_weight
+        }
+        Function::Threshold => {
+            // threshold(x, t)在x达到t时为1，否则为0；threshold(x, t, 低于时的值, 达到时的值)
+            require(2)?;
+            let values = numbers(args, lookup)?;
+            let (below, above) = if values.len() == 4 { (values[2], values[3]) } else { (0.0, 1.0) };
+            if values[0] >= values[1] { above } else { below }
+        }
+        Function::Clamp => {
+            require(3)?;
+            let values = numbers(args, lookup)?;
+            values[0].max(values[1]).min(values[2])
+        }
+        Function::Abs => {
+            require(1)?;
+            number(single(&args[0]), lookup)?.abs()
+        }
+        Function::If => {
+            require(3)?;
+            let branch = if condition(single(&args[0]), lookup)? { &args[1] } else { &args[2] };
+            return value(single(branch), lookup);
+        }
+        Function::Piecewise => {
+            require(1)?;
+            let (default, cases) = args.split_last().expect("参数个数已检查");
+            for case in cases {
+                if let Arg::Pair(when, then) = case {
+                    if condition(when, lookup)? {
+                        return Ok(Value::Number(number(then, lookup)?));
+                    }
+                }
+            }
+            number(single(default), lookup)?
+        }
+    };
+    Ok(Value::Number(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse;
+    use super::*;
+
+    // a = 2, b = 3, c = 0
+    fn lookup(name: &str) -> Option<f64> {
+        match name {
+            "a" => Some(2.0),
+            "b" => Some(3.0),
+            "c" => Some(0.0),
+            _ => None,
+        }
+    }
+
+    fn evaluated(source: &str) -> Result<f64, AlgorithmError> {
+        evaluate(&parse(source).unwrap(), &lookup)
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(evaluated("a + b * 4 - 1"), Ok(13.0));
+        assert_eq!(evaluated("12 / b / a"), Ok(2.0));
+        assert_eq!(evaluated("10 - b - a"), Ok(5.0));
+        assert_eq!(evaluated("-a * b"), Ok(-6.0));
+        assert_eq!(evaluated("1e3 * a"), Ok(2000.0));
+    }
+
+    #[test]
+    fn aggregate_functions() {
+        assert_eq!(evaluated("sum(a, b, 5)"), Ok(10.0));
+        assert_eq!(evaluated("mean(a, b)"), Ok(2.5));
+        assert_eq!(evaluated("min(a, b, c) + max(a, b)"), Ok(3.0));
+        assert_eq!(evaluated("weighted_sum(a: 0.5, b: 2)"), Ok(7.0));
+        assert_eq!(evaluated("weighted_mean(a: 1, b: 3)"), Ok(2.75));
+        assert_eq!(evaluated("clamp(b, 0, a) + clamp(-b, 0, a) + abs(c - b)"), Ok(5.0));
+    }
+
+    #[test]
+    fn threshold_is_inclusive() {
+        assert_eq!(evaluated("threshold(a, 2)"), Ok(1.0));
+        assert_eq!(evaluated("threshold(a, 2.5)"), Ok(0.0));
+        assert_eq!(evaluated("threshold(b, 3, 10, 20)"), Ok(20.0));
+        assert_eq!(evaluated("threshold(a, 3, 10, 20)"), Ok(10.0));
+    }
+
+    #[test]
+    fn piecewise_takes_first_true_case() {
+        let source = |x: &str| format!("piecewise({x} < 1: 10, {x} < 3: 20, {x} < 5: 30, 40)", x = x);
+        assert_eq!(evaluated(&source("c")), Ok(10.0));
+        // 2 < 3与2 < 5都成立，取前者
+        assert_eq!(evaluated(&source("a")), Ok(20.0));
+        assert_eq!(evaluated(&source("b")), Ok(30.0));
+        assert_eq!(evaluated(&source("(a + b)")), Ok(40.0));
+    }
+
+    #[test]
+    fn if_and_logical_operators() {
+        assert_eq!(evaluated("if(a < b, a, b)"), Ok(2.0));
+        assert_eq!(evaluated("if(not a < b or c != 0, a, b)"), Ok(3.0));
+        assert_eq!(evaluated("if(a == 2 and b >= 3, 1, 0)"), Ok(1.0));
+        // 只计算选中的分支
+        assert_eq!(evaluated("if(c == 0, a, a / c)"), Ok(2.0));
+        assert_eq!(evaluated("if(c != 0 and a / c > 1, 1, 0)"), Ok(0.0));
+    }
+
+    #[test]
+    fn division_by_zero_reports_operator_position() {
+        assert_eq!(evaluated("a + b / c"), Err(AlgorithmError::at(6, "除数为0".to_string())));
+        assert_eq!(evaluated("a / (b - 3)").unwrap_err().column, Some(2));
+        assert_eq!(evaluated("weighted_mean(a: c, b: 0)"), Err(AlgorithmError::at(0, "权重之和为0".to_string())));
+    }
+
+    #[test]
+    fn unchecked_expressions_are_errors_not_panics() {
+        for source in ["abs()", "if(a > 1, b)", "clamp(a, 1)", "threshold(a)", "piecewise()", "nope(a)"] {
+            assert!(evaluated(source).is_err(), "{}", source);
+        }
+        assert_eq!(evaluated("abs()"), Err(AlgorithmError::at(0, "abs需要至少1个参数，实际为0个".to_string())));
+        assert!(evaluated("a + (b > 1)").is_err());
+        assert!(evaluated("if(a, b, c)").is_err());
+    }
+
+    #[test]
+    fn missing_value_names_the_reference() {
+        assert_eq!(evaluated("a + 肺"), Err(AlgorithmError::at(4, "缺少肺的值".to_string())));
+    }
+}
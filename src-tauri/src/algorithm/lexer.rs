@@ -0,0 +1,202 @@
+use super::AlgorithmError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Number(f64),
+    // 不加引号的名称，可能是子节点名称或函数名
+    Ident(String),
+    // 加引号的名称，只能是子节点名称
+    Quoted(String),
+    LParen,
+    RParen,
+    Comma,
+    Colon,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    // 在源字符串中的字节范围，用于原位替换名称
+    pub start: usize,
+    pub end: usize,
+    // 第几个字符，用于报错
+    pub column: usize,
+}
+
+// 名称中可以包含中文、全角括号等非ASCII字符，全角逗号、冒号和引号除外
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || (!c.is_ascii() && !c.is_whitespace() && c != '，' && c != '：' && c != '“' && c != '”')
+}
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, AlgorithmError> {
+    let chars = source.char_indices().collect::<Vec<(usize, char)>>();
+    // 第i个字符的字节偏移
+    let offset = |i: usize| chars.get(i).map(|&(offset, _)| offset).unwrap_or(source.len());
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let column = i;
+        let next = chars.get(i + 1).map(|&(_, c)| c);
+        let kind;
+        if c.is_ascii_digit() || (c == '.' && next.map_or(false, |c| c.is_ascii_digit())) {
+            let mut j = i;
+            while j < chars.len() && (chars[j].1.is_ascii_digit() || chars[j].1 == '.') {
+                j += 1;
+            }
+            // 科学计数法的指数部分，如1e3、2.5E-2
+            if j < chars.len() && (chars[j].1 == 'e' || chars[j].1 == 'E') {
+                let mut k = j + 1;
+                if k < chars.len() && (chars[k].1 == '+' || chars[k].1 == '-') {
+                    k += 1;
+                }
+                if k < chars.len() && chars[k].1.is_ascii_digit() {
+                    while k < chars.len() && chars[k].1.is_ascii_digit() {
+                        k += 1;
+                    }
+                    j = k;
+                }
+            }
+            let text = &source[start..offset(j)];
+            // 数字后紧跟名称，如"1e"或"2型"，以数字开头的名称需加引号
+            if j < chars.len() && is_ident_char(chars[j].1) {
+                let mut k = j;
+                while k < chars.len() && is_ident_char(chars[k].1) {
+                    k += 1;
+                }
+                let text = &source[start..offset(k)];
+                return Err(AlgorithmError::at(column, format!("无效的数字{}，以数字开头的名称需加引号", text)));
+            }
+            let value = text.parse::<f64>().map_err(|_| AlgorithmError::at(column, format!("无效的数字{}", text)))?;
+            kind = TokenKind::Number(value);
+            i = j;
+        } else if c == '"' || c == '“' {
+            let closing = if c == '"' { '"' } else { '”' };
+            let mut j = i + 1;
+            while j < chars.len() && chars[j].1 != closing {
+                j += 1;
+            }
+            if j == chars.len() {
+                return Err(AlgorithmError::at(column, "引号未闭合".to_string()));
+            }
+            kind = TokenKind::Quoted(source[offset(i + 1)..offset(j)].to_string());
+            i = j + 1;
+        } else if is_ident_char(c) {
+            let mut j = i;
+            while j < chars.len() && is_ident_char(chars[j].1) {
+                j += 1;
+            }
+            kind = match &source[start..offset(j)] {
+                "and" => TokenKind::And,
+                "or" => TokenKind::Or,
+                "not" => TokenKind::Not,
+                ident => TokenKind::Ident(ident.to_string()),
+            };
+            i = j;
+        } else {
+            let (operator, length) = match (c, next) {
+                ('<', Some('=')) => (TokenKind::Le, 2),
+                ('>', Some('=')) => (TokenKind::Ge, 2),
+                ('=', Some('=')) => (TokenKind::Eq, 2),
+                ('!', Some('=')) => (TokenKind::Ne, 2),
+                ('&', Some('&')) => (TokenKind::And, 2),
+                ('|', Some('|')) => (TokenKind::Or, 2),
+                ('<', _) => (TokenKind::Lt, 1),
+                ('>', _) => (TokenKind::Gt, 1),
+                ('!', _) => (TokenKind::Not, 1),
+                ('(', _) => (TokenKind::LParen, 1),
+                (')', _) => (TokenKind::RParen, 1),
+                (',', _) | ('，', _) => (TokenKind::Comma, 1),
+                (':', _) | ('：', _) => (TokenKind::Colon, 1),
+                ('+', _) => (TokenKind::Plus, 1),
+                ('-', _) => (TokenKind::Minus, 1),
+                ('*', _) => (TokenKind::Star, 1),
+                ('/', _) => (TokenKind::Slash, 1),
+                _ => return Err(AlgorithmError::at(column, format!("无法识别的字符{}", c))),
+            };
+            kind = operator;
+            i += length;
+        }
+        tokens.push(Token { kind, start, end: offset(i), column });
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        tokenize(source).unwrap().into_iter().map(|token| token.kind).collect()
+    }
+
+    fn error_column(source: &str) -> Option<usize> {
+        tokenize(source).unwrap_err().column
+    }
+
+    #[test]
+    fn operators_and_full_width_punctuation() {
+        use TokenKind::*;
+        assert_eq!(kinds("a<=b && !c || d != 1"), [Ident("a".into()), Le, Ident("b".into()), And, Not, Ident("c".into()), Or, Ident("d".into()), Ne, Number(1.0)]);
+        assert_eq!(kinds("weighted_sum(心：0.4，肺: .6)"), [
+            Ident("weighted_sum".into()), LParen, Ident("心".into()), Colon, Number(0.4), Comma, Ident("肺".into()), Colon, Number(0.6), RParen,
+        ]);
+        assert_eq!(kinds("not x and y or z"), [Not, Ident("x".into()), And, Ident("y".into()), Or, Ident("z".into())]);
+    }
+
+    #[test]
+    fn names_may_contain_non_ascii_punctuation() {
+        assert_eq!(kinds("全身（组织）+ 肝-1"), [
+            TokenKind::Ident("全身（组织）".into()), TokenKind::Plus, TokenKind::Ident("肝".into()), TokenKind::Minus, TokenKind::Number(1.0),
+        ]);
+        assert_eq!(kinds("\"全身 (组织)\" * “a\"b”"), [TokenKind::Quoted("全身 (组织)".into()), TokenKind::Star, TokenKind::Quoted("a\"b".into())]);
+    }
+
+    #[test]
+    fn exponent_literals() {
+        assert_eq!(kinds("1e3 + 2.5E-2 * 4e+1"), [
+            TokenKind::Number(1000.0), TokenKind::Plus, TokenKind::Number(0.025), TokenKind::Star, TokenKind::Number(40.0),
+        ]);
+    }
+
+    #[test]
+    fn number_followed_by_name_is_rejected() {
+        assert_eq!(error_column("a + 1e"), Some(4));
+        let error = tokenize("2型 + 1").unwrap_err();
+        assert_eq!(error.column, Some(0));
+        assert!(error.message.contains("2型") && error.message.contains("引号"));
+        assert_eq!(error_column("1.2.3"), Some(0));
+    }
+
+    #[test]
+    fn columns_count_characters_not_bytes() {
+        let tokens = tokenize("心脏 + 肺").unwrap();
+        assert_eq!(tokens.iter().map(|token| token.column).collect::<Vec<usize>>(), [0, 3, 5]);
+        // 字节范围用于原位替换
+        assert_eq!((tokens[2].start, tokens[2].end), ("心脏 + ".len(), "心脏 + 肺".len()));
+    }
+
+    #[test]
+    fn errors_report_column() {
+        assert_eq!(error_column("肝 + \"肾"), Some(4));
+        assert_eq!(error_column("肝 # 肾"), Some(2));
+        assert_eq!(error_column("a = b"), Some(2));
+    }
+}
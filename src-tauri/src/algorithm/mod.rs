@@ -0,0 +1,209 @@
+// 组合模型的算法：由子节点名称、数字、运算符和函数组成的表达式，例如
+//   weighted_sum(心: 0.4, 肺: 0.6)
+//   piecewise(血压 < 90: 0, 血压 < 140: 100, 60)
+//   if(min(肝, 肾) >= 60 and 脾 > 0, mean(肝, 肾), 0)
+// 名称中含有空格或运算符、或以数字开头时用引号括起来，例如"全身(组织)"；数字可以写作1e3
+// 支持的函数：
+//   sum、mean、min、max：数值参数，至少1个
+//   weighted_sum、weighted_mean：“值: 权重”参数，至少1个
+//   threshold(x, t)：x达到t时为1，否则为0；threshold(x, t, 低于时的值, 达到时的值)
+//   piecewise(条件: 值, ..., 默认值)：取第一个成立的条件对应的值
+//   if(条件, 值, 值)、clamp(x, 下限, 上限)、abs(x)
+// “待定”等占位符表示算法尚未确定，不参与检查和计算
+use std::fmt;
+
+mod check;
+mod eval;
+mod lexer;
+mod parser;
+
+pub use check::{check, Type};
+pub use eval::evaluate;
+pub use parser::{parse, Expr};
+
+const PLACEHOLDERS: [&str; 3] = ["", "待定", "未定义算法"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlgorithmError {
+    pub message: String,
+    // 出错的字符位置（从0开始）
+    pub column: Option<usize>,
+}
+
+impl AlgorithmError {
+    pub fn new(message: String) -> Self {
+        AlgorithmError { message, column: None }
+    }
+
+    pub fn at(column: usize, message: String) -> Self {
+        AlgorithmError { message, column: Some(column) }
+    }
+}
+
+impl fmt::Display for AlgorithmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "第{}个字符处：{}", column + 1, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    WeightedSum,
+    WeightedMean,
+    Threshold,
+    Piecewise,
+    If,
+    Clamp,
+    Abs,
+}
+
+impl Function {
+    fn lookup(name: &str) -> Option<Function> {
+        Some(match name {
+            "sum" => Function::Sum,
+            "mean" => Function::Mean,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "weighted_sum" => Function::WeightedSum,
+            "weighted_mean" => Function::WeightedMean,
+            "threshold" => Function::Threshold,
+            "piecewise" => Function::Piecewise,
+            "if" => Function::If,
+            "clamp" => Function::Clamp,
+            "abs" => Function::Abs,
+            _ => return None,
+        })
+    }
+}
+
+pub fn is_placeholder(source: &str) -> bool {
+    PLACEHOLDERS.contains(&source.trim())
+}
+
+// 解析并检查算法，结果必须是数值
+pub fn compile(source: &str, children: &[String]) -> Result<Expr, AlgorithmError> {
+    let expr = parse(source)?;
+    if check(&expr, children)? != Type::Number {
+        return Err(AlgorithmError::new("算法的结果应为数值，实际为条件".to_string()));
+    }
+    Ok(expr)
+}
+
+// 占位符视为合法的算法
+pub fn validate(source: &str, children: &[String]) -> Result<(), AlgorithmError> {
+    if is_placeholder(source) {
+        return Ok(());
+    }
+    compile(source, children).map(|_| ())
+}
+
+// 子节点改名后同步修改算法中对它的引用，保留算法的其余部分不变
+// 算法无法解析或未引用该名称时返回None
+pub fn rename_reference(source: &str, old_name: &str, new_name: &str) -> Option<String> {
+    rename_references(source, &[(old_name, new_name)])
+}
+
+// 同时修改多个子节点的名称，互换名称时也不会相互影响；renames中的元素为(原名称, 新名称)
+pub fn rename_references(source: &str, renames: &[(&str, &str)]) -> Option<String> {
+    if is_placeholder(source) {
+        return None;
+    }
+    let tokens = lexer::tokenize(source).ok()?;
+    let new_name_of = |name: &str| renames.iter().find(|(old_name, _)| *old_name == name).map(|(_, new_name)| *new_name);
+    let mut result = String::new();
+    let mut copied = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        let new_name = match &token.kind {
+            lexer::TokenKind::Quoted(name) => new_name_of(name),
+            lexer::TokenKind::Ident(name) if tokens.get(i + 1).map(|next| &next.kind) != Some(&lexer::TokenKind::LParen) => new_name_of(name),
+            _ => None,
+        };
+        if let Some(new_name) = new_name {
+            result.push_str(&source[copied..token.start]);
+            result.push_str(&quote_name(new_name));
+            copied = token.end;
+        }
+    }
+    if copied == 0 {
+        return None;
+    }
+    result.push_str(&source[copied..]);
+    Some(result)
+}
+
+// 不能作为一个名称直接写出时加引号
+fn quote_name(name: &str) -> String {
+    let tokens = lexer::tokenize(name);
+    match tokens.as_deref() {
+        Ok([token]) if token.kind == lexer::TokenKind::Ident(name.to_string()) => name.to_string(),
+        _ if !name.contains('"') => format!("\"{}\"", name),
+        _ => format!("“{}”", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn children(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn placeholders_skip_checking() {
+        for source in ["", "  ", "待定", " 未定义算法 "] {
+            assert!(is_placeholder(source));
+            assert_eq!(validate(source, &[]), Ok(()));
+            assert_eq!(rename_reference(source, "a", "b"), None);
+        }
+        assert!(!is_placeholder("待定 + 1"));
+        // 不作为占位符时需要能够解析
+        assert!(compile("", &[]).is_err());
+        assert!(validate("待定 + 1", &[]).is_err());
+    }
+
+    #[test]
+    fn compile_requires_a_number() {
+        let children = children(&["a"]);
+        assert!(compile("a + 1", &children).is_ok());
+        assert_eq!(compile("a > 1", &children).unwrap_err().message, "算法的结果应为数值，实际为条件");
+        assert_eq!(validate("a + b", &children).unwrap_err().column, Some(4));
+    }
+
+    #[test]
+    fn rename_keeps_the_rest_of_the_source() {
+        assert_eq!(rename_reference("sum(心,  肺)*2", "肺", "右肺"), Some("sum(心,  右肺)*2".to_string()));
+        assert_eq!(rename_reference("\"心\" + 心", "心", "心脏"), Some("心脏 + 心脏".to_string()));
+        assert_eq!(rename_reference("a + b", "c", "d"), None);
+        assert_eq!(rename_reference("a + \"b", "a", "d"), None);
+    }
+
+    #[test]
+    fn rename_does_not_touch_function_names() {
+        assert_eq!(rename_reference("sum(sum, 1)", "sum", "total"), Some("sum(total, 1)".to_string()));
+    }
+
+    #[test]
+    fn rename_quotes_names_that_are_not_plain_identifiers() {
+        assert_eq!(rename_reference("a * 2", "a", "全身 (组织)"), Some("\"全身 (组织)\" * 2".to_string()));
+        assert_eq!(rename_reference("a * 2", "a", "心-肺"), Some("\"心-肺\" * 2".to_string()));
+        assert_eq!(rename_reference("a * 2", "a", "2型"), Some("\"2型\" * 2".to_string()));
+        assert_eq!(rename_reference("a * 2", "a", "and"), Some("\"and\" * 2".to_string()));
+        // 名称中有半角引号时改用全角引号
+        assert_eq!(rename_reference("a * 2", "a", "x\"y"), Some("“x\"y” * 2".to_string()));
+        let renamed = rename_reference("a * 2", "a", "全身 (组织)").unwrap();
+        assert!(validate(&renamed, &children(&["全身 (组织)"])).is_ok());
+    }
+
+    #[test]
+    fn rename_references_swaps_names() {
+        assert_eq!(rename_references("a - b", &[("a", "b"), ("b", "a")]), Some("b - a".to_string()));
+    }
+}
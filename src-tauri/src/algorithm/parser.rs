@@ -0,0 +1,372 @@
+// 语法：
+// expr       := or
+// or         := and (("or" | "||") and)*
+// and        := not (("and" | "&&") not)*
+// not        := ("not" | "!") not | comparison
+// comparison := additive (("<" | "<=" | ">" | ">=" | "==" | "!=") additive)?
+// additive   := term (("+" | "-") term)*
+// term       := unary (("*" | "/") unary)*
+// unary      := "-" unary | primary
+// primary    := number | name | ident "(" args ")" | "(" expr ")"
+// args       := arg ("," arg)*
+// arg        := expr (":" expr)?
+use super::lexer::{tokenize, Token, TokenKind};
+use super::AlgorithmError;
+
+// 表达式最多嵌套的层数，括号、函数参数、一元运算符以及连续的二元运算符各算一层
+// 限制语法树的深度，以免解析、检查和计算时递归过深导致栈溢出
+const MAX_DEPTH: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    // 对子节点的引用
+    Ref { name: String, position: usize },
+    Unary { op: UnaryOp, operand: Box<Expr>, position: usize },
+    Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr>, position: usize },
+    Call { function: String, args: Vec<Arg>, position: usize },
+}
+
+// 函数参数，"a: 0.3"形式的参数用于加权和分段函数
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Single(Expr),
+    Pair(Expr, Expr),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // 表达式的字符数，用于在表达式结尾报错
+    length: usize,
+    // 当前的嵌套层数
+    depth: usize,
+}
+
+pub fn parse(source: &str) -> Result<Expr, AlgorithmError> {
+    let tokens = tokenize(source)?;
+    if tokens.is_empty() {
+        return Err(AlgorithmError::new("算法为空".to_string()));
+    }
+    let mut parser = Parser { tokens, pos: 0, length: source.chars().count(), depth: 0 };
+    let expr = parser.expr()?;
+    if let Some(token) = parser.peek() {
+        return Err(AlgorithmError::at(token.column, "表达式后有多余的内容".to_string()));
+    }
+    Ok(expr)
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_kind(&self) -> Option<&TokenKind> {
+        self.peek().map(|token| &token.kind)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // 当前位置，用于报错
+    fn position(&self) -> usize {
+        self.peek().map(|token| token.column).unwrap_or(self.length)
+    }
+
+    fn error(&self, message: &str) -> AlgorithmError {
+        match self.peek() {
+            Some(_) => AlgorithmError::at(self.position(), message.to_string()),
+            None => AlgorithmError::at(self.length, format!("{}，但表达式已结束", message)),
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, message: &str) -> Result<(), AlgorithmError> {
+        if self.peek_kind() == Some(&kind) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, AlgorithmError> {
+        self.or()
+    }
+
+    // 进入下一层嵌套，超过MAX_DEPTH时在position处报错；调用者结束这一层时需减回depth
+    fn enter(&mut self, position: usize) -> Result<(), AlgorithmError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(AlgorithmError::at(position, format!("表达式嵌套超过{}层，较长的加法可改用sum", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // 在下一层嵌套中解析，position为开始这一层的括号或运算符
+    fn nested<T>(&mut self, position: usize, parse: impl FnOnce(&mut Self) -> Result<T, AlgorithmError>) -> Result<T, AlgorithmError> {
+        self.enter(position)?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn binary_chain(
+        &mut self,
+        operand: fn(&mut Self) -> Result<Expr, AlgorithmError>,
+        operator: fn(&TokenKind) -> Option<BinaryOp>,
+    ) -> Result<Expr, AlgorithmError> {
+        // 连续的运算符构成左结合的链，每多一个运算符语法树深一层
+        let depth = self.depth;
+        let mut left = operand(self)?;
+        while let Some(op) = self.peek_kind().and_then(operator) {
+            let position = self.position();
+            if let Err(error) = self.enter(position) {
+                self.depth = depth;
+                return Err(error);
+            }
+            self.pos += 1;
+            let right = operand(self)?;
+            left = Expr::Binary { op, left: Box::new(left), right: Box::new(right), position };
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, AlgorithmError> {
+        self.binary_chain(Self::and, |kind| match kind {
+            TokenKind::Or => Some(BinaryOp::Or),
+            _ => None,
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr, AlgorithmError> {
+        self.binary_chain(Self::not, |kind| match kind {
+            TokenKind::And => Some(BinaryOp::And),
+            _ => None,
+        })
+    }
+
+    fn not(&mut self) -> Result<Expr, AlgorithmError> {
+        if self.peek_kind() == Some(&TokenKind::Not) {
+            let position = self.position();
+            self.pos += 1;
+            let operand = self.nested(position, Self::not)?;
+            return Ok(Expr::Unary { op: UnaryOp::Not, operand: Box::new(operand), position });
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, AlgorithmError> {
+        let left = self.additive()?;
+        let op = match self.peek_kind() {
+            Some(TokenKind::Lt) => BinaryOp::Lt,
+            Some(TokenKind::Le) => BinaryOp::Le,
+            Some(TokenKind::Gt) => BinaryOp::Gt,
+            Some(TokenKind::Ge) => BinaryOp::Ge,
+            Some(TokenKind::Eq) => BinaryOp::Eq,
+            Some(TokenKind::Ne) => BinaryOp::Ne,
+            _ => return Ok(left),
+        };
+        let position = self.position();
+        self.pos += 1;
+        let right = self.additive()?;
+        Ok(Expr::Binary { op, left: Box::new(left), right: Box::new(right), position })
+    }
+
+    fn additive(&mut self) -> Result<Expr, AlgorithmError> {
+        self.binary_chain(Self::term, |kind| match kind {
+            TokenKind::Plus => Some(BinaryOp::Add),
+            TokenKind::Minus => Some(BinaryOp::Sub),
+            _ => None,
+        })
+    }
+
+    fn term(&mut self) -> Result<Expr, AlgorithmError> {
+        self.binary_chain(Self::unary, |kind| match kind {
+            TokenKind::Star => Some(BinaryOp::Mul),
+            TokenKind::Slash => Some(BinaryOp::Div),
+            _ => None,
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr, AlgorithmError> {
+        if self.peek_kind() == Some(&TokenKind::Minus) {
+            let position = self.position();
+            self.pos += 1;
+            let operand = self.nested(position, Self::unary)?;
+            return Ok(Expr::Unary { op: UnaryOp::Neg, operand: Box::new(operand), position });
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, AlgorithmError> {
+        let position = self.position();
+        let token = match self.advance() {
+            Some(token) => token,
+            None => return Err(AlgorithmError::at(self.length, "表达式不完整".to_string())),
+        };
+        match token.kind {
+            TokenKind::Number(value) => Ok(Expr::Number(value)),
+            TokenKind::Quoted(name) => Ok(Expr::Ref { name, position }),
+            TokenKind::Ident(name) => {
+                if self.peek_kind() != Some(&TokenKind::LParen) {
+                    return Ok(Expr::Ref { name, position });
+                }
+                self.pos += 1;
+                let mut args = Vec::new();
+                if self.peek_kind() != Some(&TokenKind::RParen) {
+                    loop {
+                        args.push(self.nested(self.position(), Self::arg)?);
+                        if self.peek_kind() == Some(&TokenKind::Comma) {
+                            self.pos += 1;
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                self.expect(TokenKind::RParen, "缺少右括号")?;
+                Ok(Expr::Call { function: name, args, position })
+            }
+            TokenKind::LParen => {
+                let expr = self.nested(position, Self::expr)?;
+                self.expect(TokenKind::RParen, "缺少右括号")?;
+                Ok(expr)
+            }
+            _ => Err(AlgorithmError::at(position, "此处应为数字、名称或括号".to_string())),
+        }
+    }
+
+    fn arg(&mut self) -> Result<Arg, AlgorithmError> {
+        let first = self.expr()?;
+        if self.peek_kind() == Some(&TokenKind::Colon) {
+            self.pos += 1;
+            let second = self.expr()?;
+            return Ok(Arg::Pair(first, second));
+        }
+        Ok(Arg::Single(first))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 以前缀形式显示语法树，便于比较结合方式
+    fn show(expr: &Expr) -> String {
+        match expr {
+            Expr::Number(value) => value.to_string(),
+            Expr::Ref { name, .. } => name.clone(),
+            Expr::Unary { op, operand, .. } => format!("({:?} {})", op, show(operand)),
+            Expr::Binary { op, left, right, .. } => format!("({:?} {} {})", op, show(left), show(right)),
+            Expr::Call { function, args, .. } => {
+                let args = args.iter().map(|arg| match arg {
+                    Arg::Single(expr) => show(expr),
+                    Arg::Pair(first, second) => format!("{}: {}", show(first), show(second)),
+                }).collect::<Vec<String>>();
+                format!("{}({})", function, args.join(", "))
+            }
+        }
+    }
+
+    fn parsed(source: &str) -> String {
+        show(&parse(source).unwrap())
+    }
+
+    fn error_column(source: &str) -> Option<usize> {
+        parse(source).unwrap_err().column
+    }
+
+    #[test]
+    fn arithmetic_precedence_and_left_associativity() {
+        assert_eq!(parsed("a + b * c"), "(Add a (Mul b c))");
+        assert_eq!(parsed("(a + b) * c"), "(Mul (Add a b) c)");
+        assert_eq!(parsed("a - b - c"), "(Sub (Sub a b) c)");
+        assert_eq!(parsed("a / b / c"), "(Div (Div a b) c)");
+        assert_eq!(parsed("a - b * c / d + e"), "(Add (Sub a (Div (Mul b c) d)) e)");
+    }
+
+    #[test]
+    fn unary_minus_binds_tighter_than_multiplication() {
+        assert_eq!(parsed("-a * b"), "(Mul (Neg a) b)");
+        assert_eq!(parsed("a * -b"), "(Mul a (Neg b))");
+        assert_eq!(parsed("--a"), "(Neg (Neg a))");
+    }
+
+    #[test]
+    fn logical_precedence() {
+        // 比较高于not，not高于and，and高于or
+        assert_eq!(parsed("a < b + 1 and c >= d"), "(And (Lt a (Add b 1)) (Ge c d))");
+        assert_eq!(parsed("a > 1 or b > 1 and c > 1"), "(Or (Gt a 1) (And (Gt b 1) (Gt c 1)))");
+        assert_eq!(parsed("not a > 1 and b > 1"), "(And (Not (Gt a 1)) (Gt b 1))");
+        assert_eq!(parsed("a > 1 || b > 1 && !(c == 1)"), "(Or (Gt a 1) (And (Gt b 1) (Not (Eq c 1))))");
+    }
+
+    #[test]
+    fn comparisons_do_not_chain() {
+        assert_eq!(error_column("a < b < c"), Some(6));
+    }
+
+    #[test]
+    fn calls_with_single_and_pair_arguments() {
+        assert_eq!(parsed("weighted_sum(心: 0.4, 肺: 0.6)"), "weighted_sum(心: 0.4, 肺: 0.6)");
+        assert_eq!(parsed("piecewise(x < 90: 0, x < 140: 100, 60)"), "piecewise((Lt x 90): 0, (Lt x 140): 100, 60)");
+        assert_eq!(parsed("max()"), "max()");
+        // 名称后不跟括号时是引用，引号中的名称不会被当作函数
+        assert_eq!(parsed("sum + \"sum\""), "(Add sum sum)");
+        assert_eq!(error_column("\"sum\"(a)"), Some(5));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        let error = parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        // 报错的位置为超出限制的左括号
+        assert_eq!(error.column, Some(MAX_DEPTH));
+        assert!(error.message.contains("嵌套"));
+        // 远超限制的输入也只报错，不会栈溢出
+        assert!(parse(&nested(100_000)).is_err());
+        assert!(parse(&format!("{}a", "-".repeat(100_000))).is_err());
+        assert!(parse(&format!("{}a > 1", "not ".repeat(100_000))).is_err());
+        assert!(parse(&format!("a{}", " + a".repeat(100_000))).is_err());
+        assert!(parse(&format!("{}a{}", "sum(".repeat(100_000), ")".repeat(100_000))).is_err());
+        assert!(parse(&format!("a{}", " + a".repeat(MAX_DEPTH))).is_ok());
+    }
+
+    #[test]
+    fn errors_report_column() {
+        assert_eq!(parse("").unwrap_err().column, None);
+        assert_eq!(error_column("a + b c"), Some(6));
+        assert_eq!(error_column("a * (b + c"), Some(10));
+        assert!(parse("a * (b + c").unwrap_err().message.contains("表达式已结束"));
+        assert_eq!(error_column("a +"), Some(3));
+        assert_eq!(error_column("a + * b"), Some(4));
+        assert_eq!(error_column("sum(a, b"), Some(8));
+        assert_eq!(error_column("心脏 + )"), Some(5));
+    }
+}
@@ -0,0 +1,336 @@
+// 无界面的命令行模式，供脚本和持续集成检查、修改模型
+// 与界面共用读取、修改和保存的逻辑，修改类子命令成功后写回模型文件
+// 以单独的控制台程序model-cli运行（见同目录的main.rs），界面程序的"app --cli"也包含这个文件：
+// 界面程序在Windows的发布版本中属于窗口子系统，不连接控制台，"app --cli"的输出会丢失，
+// 退出码也无法在命令提示符中等待，因此只在其他平台和调试版本中使用
+use app::diagram::DiagramFormat;
+use app::error::ModelError;
+use app::flat::{self, FlatFormat};
+use app::journal;
+use app::lint::{Diagnostic, Severity};
+use app::{config, find_by_name, model_label, ImportReport, Model, ModelGraph, ModelId};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+pub const USAGE: &str = "用法：model-cli <子命令> [参数] [选项]
+
+子命令：
+  lint                         检查模型，有错误时退出码为1
+  rename <模型> <新名称>       重命名模型，规则与界面中相同
+  add <父节点> <名称>          在父节点中添加叶节点
+  delete <父节点> <模型>       从父节点中删除模型，不再被引用的模型一并删除
+  set-algorithm <模型> <算法>  修改模型的算法
+  evaluate <取值文件>          按JSON文件中叶节点名称到取值的映射计算，结果以JSON输出
+  import <文件>                导入边表（.csv）或大纲（.txt），替换当前的模型并输出差异
+  export <路径>                将模型另存为模型文件，.csv为边表、.txt为大纲，不修改原文件
+  graph <格式>                 将模型图输出为dot（Graphviz）或mermaid
+
+选项：
+  --models <路径>  模型文件或旧版的模型目录
+  --root <名称>    根节点，未指定时自动推断
+  --index <位置>   add时插入的位置，从0开始，默认加在最后
+  --depth <层数>   graph输出的层数，默认不限
+  --output <路径>  修改后写入的文件，默认写回模型文件
+  --dry-run        只检查修改能否执行，不写入文件
+
+模型以名称指定，重名时写作#<ID>
+在Linux、macOS上也可以用界面程序运行：app --cli <子命令> ...";
+
+// 进程的退出码
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+// 子命令、参数名称以及是否修改模型
+const COMMANDS: &[(&str, &[&str], bool)] = &[
+    ("lint", &[], false),
+    ("rename", &["模型", "新名称"], true),
+    ("add", &["父节点", "名称"], true),
+    ("delete", &["父节点", "模型"], true),
+    ("set-algorithm", &["模型", "算法"], true),
+    ("evaluate", &["取值文件"], false),
+    ("import", &["文件"], true),
+    ("export", &["路径"], false),
+    ("graph", &["格式"], false),
+];
+
+#[derive(Debug, Default)]
+struct Options {
+    command: String,
+    args: Vec<String>,
+    // 交给config解析的--models和--root
+    config_args: Vec<String>,
+    index: Option<usize>,
+    depth: Option<usize>,
+    output: Option<String>,
+    dry_run: bool,
+}
+
+// args为子命令及之后的参数（不含程序名和"--cli"），返回进程的退出码
+pub fn run(args: Vec<String>) -> i32 {
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let options = match parse_options(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    match execute(options) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+// 返回的错误为用法错误的说明
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // 算法等参数中可能有"="，只拆分选项
+        let (key, value) = match arg.split_once('=') {
+            Some((key, value)) if key.starts_with("--") => (key.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        if key == "--dry-run" {
+            options.dry_run = true;
+            continue;
+        }
+        if !key.starts_with("--") {
+            positional.push(arg);
+            continue;
+        }
+        let value = match value.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(format!("选项{}缺少取值", key)),
+        };
+        match key.as_str() {
+            "--models" | "--root" => options.config_args.extend([key, value]),
+            "--index" => {
+                let index = value.parse().map_err(|_| format!("选项--index的取值{:?}不是有效的位置", value))?;
+                options.index = Some(index);
+            }
+            "--depth" => {
+                let depth = value.parse().map_err(|_| format!("选项--depth的取值{:?}不是有效的层数", value))?;
+                options.depth = Some(depth);
+            }
+            "--output" => options.output = Some(value),
+            _ => return Err(format!("未知的选项{}", key)),
+        }
+    }
+    let mut positional = positional.into_iter();
+    options.command = positional.next().ok_or_else(|| "缺少子命令".to_string())?;
+    options.args = positional.collect();
+    let (_, params, modifies) = COMMANDS.iter().find(|(name, _, _)| *name == options.command)
+        .ok_or_else(|| format!("未知的子命令{}", options.command))?;
+    if options.args.len() != params.len() {
+        let params = params.iter().map(|param| format!("<{}>", param)).collect::<Vec<String>>();
+        return Err(format!("子命令{}需要{}个参数：{}", options.command, params.len(), params.join(" ")));
+    }
+    if options.index.is_some() && options.command != "add" {
+        return Err("选项--index只用于add".to_string());
+    }
+    if options.depth.is_some() && options.command != "graph" {
+        return Err("选项--depth只用于graph".to_string());
+    }
+    if options.command == "import" && FlatFormat::from_path(&options.args[0]).is_none() {
+        return Err(format!("无法识别{}的格式，边表的扩展名为.csv，大纲为.txt", options.args[0]));
+    }
+    if options.command == "graph" && DiagramFormat::parse(&options.args[0]).is_none() {
+        return Err(format!("未知的格式{}，可用dot或mermaid", options.args[0]));
+    }
+    if (options.output.is_some() || options.dry_run) && !modifies {
+        return Err(format!("子命令{}不修改模型，不能使用--output或--dry-run", options.command));
+    }
+    Ok(options)
+}
+
+fn execute(options: Options) -> Result<i32> {
+    let (models_file_path, root_name) = config::load_from(options.config_args.into_iter())?;
+    let mut graph = ModelGraph::load(&models_file_path, root_name.as_deref())?;
+    let pending_journal = journal::read_entries(&journal::journal_path(&models_file_path))?;
+    let args = options.args.iter().map(String::as_str).collect::<Vec<&str>>();
+    match (options.command.as_str(), args.as_slice()) {
+        ("lint", []) => return Ok(lint(&graph)),
+        ("evaluate", [values_file_path]) => {
+            evaluate_values(values_file_path, &graph)?;
+            return Ok(0);
+        }
+        ("export", [file_path]) => {
+            match FlatFormat::from_path(file_path) {
+                Some(format) => flat::write_file(format, file_path, &graph.file_models()?)?,
+                None => graph.save(file_path)?,
+            }
+            println!("已导出到{}", file_path);
+            return Ok(0);
+        }
+        ("graph", [format]) => {
+            let format = DiagramFormat::parse(format).expect("格式已在parse_options中检查");
+            print!("{}", graph.export_graph(format, None, options.depth)?);
+            return Ok(0);
+        }
+        _ => {}
+    }
+    // 直接写入模型文件会使日志中的记录无法再重放
+    if !pending_journal.is_empty() {
+        return Err(anyhow::anyhow!("日志中有{}条未保存的修改，请先在界面中恢复或丢弃", pending_journal.len()));
+    }
+    let summary = match (options.command.as_str(), args.as_slice()) {
+        ("rename", [name, new_name]) => {
+            let id = resolve(name, graph.models())?;
+            let response = graph.rename(id, new_name)?;
+            if response.id == id {
+                format!("已将{}重命名为{}", name, response.new_name)
+            } else {
+                format!("已将{}合并到同名模型{}", name, response.new_name)
+            }
+        }
+        ("add", [parent_name, name]) => {
+            let parent_id = resolve(parent_name, graph.models())?;
+            // 界面中添加的模型先取临时名称再重命名，这里直接使用给定的名称，重名时不合并
+            if graph.unique_names() {
+                if let Some(existing) = find_by_name(name, graph.models()).first() {
+                    return Err(ModelError::name_conflict(&graph.models()[existing]).into());
+                }
+            }
+            let new_id = graph.next_id();
+            graph.add_node(parent_id, options.index, new_id, name)?;
+            format!("已在{}中添加{}（ID {}）", parent_name, name, new_id)
+        }
+        ("delete", [parent_name, name]) => {
+            let parent_id = resolve(parent_name, graph.models())?;
+            let id = resolve(name, graph.models())?;
+            let count = graph.models().len();
+            graph.delete_node(parent_id, id)?;
+            format!("已从{}中删除{}，共移除{}个模型", parent_name, name, count - graph.models().len())
+        }
+        ("set-algorithm", [name, algorithm]) => {
+            let id = resolve(name, graph.models())?;
+            graph.update_algorithm(id, algorithm)?;
+            format!("已修改{}的算法", name)
+        }
+        ("import", [file_path]) => {
+            let format = FlatFormat::from_path(file_path).expect("格式已在parse_options中检查");
+            let report = graph.import(flat::read_file(format, file_path)?)?;
+            print_import_report(&report);
+            if report.is_empty() {
+                println!("导入的内容与当前模型相同");
+                return Ok(0);
+            }
+            format!("已导入{}", file_path)
+        }
+        _ => unreachable!("子命令及参数个数已在parse_options中检查"),
+    };
+    if options.dry_run {
+        println!("{}（--dry-run，未写入文件）", summary);
+        return Ok(0);
+    }
+    let output = options.output.unwrap_or_else(|| models_file_path.clone());
+    if Path::new(&output).is_dir() {
+        return Err(anyhow::anyhow!("{:?}是旧版的模型目录，请用--output指定要写入的模型文件", output));
+    }
+    graph.save(&output)?;
+    println!("{}，已写入{}", summary, output);
+    Ok(0)
+}
+
+// 按名称查找模型，也可以写作#<ID>
+fn resolve(name: &str, models: &HashMap<ModelId, Model>) -> Result<ModelId> {
+    if let Some(id) = name.strip_prefix('#').and_then(|id| id.parse::<ModelId>().ok()) {
+        return match models.get(&id) {
+            Some(model) => Ok(model.id),
+            None => Err(ModelError::not_found(id).into()),
+        };
+    }
+    match find_by_name(name, models).as_slice() {
+        [id] => Ok(*id),
+        [] => Err(anyhow::anyhow!("未找到名为{}的模型", name)),
+        ids => {
+            let ids = ids.iter().map(|id| format!("#{}", id)).collect::<Vec<String>>();
+            Err(anyhow::anyhow!("有{}个模型名为{}，请用ID指定：{}", ids.len(), name, ids.join("、")))
+        }
+    }
+}
+
+// 输出中表示模型的键，重名的模型写作#<ID>，与resolve的写法一致
+fn output_key(id: ModelId, models: &HashMap<ModelId, Model>) -> String {
+    let name = model_label(id, models);
+    if find_by_name(&name, models).len() > 1 {
+        format!("#{}", id)
+    } else {
+        name
+    }
+}
+
+// 只有警告时仍视为通过
+// 错误输出到标准错误，警告输出到标准输出
+pub fn print_diagnostic(diagnostic: &Diagnostic) {
+    match diagnostic.severity {
+        Severity::Error => eprintln!("错误[{}]：{}", diagnostic.code, diagnostic.message),
+        Severity::Warning => println!("警告[{}]：{}", diagnostic.code, diagnostic.message),
+    }
+}
+
+fn lint(graph: &ModelGraph) -> i32 {
+    let diagnostics = graph.diagnostics();
+    diagnostics.iter().for_each(print_diagnostic);
+    let errors = diagnostics.iter().filter(|diagnostic| diagnostic.severity == Severity::Error).count();
+    println!("发现{}个错误，{}个警告", errors, diagnostics.len() - errors);
+    if errors > 0 {
+        EXIT_FAILURE
+    } else {
+        0
+    }
+}
+
+#[derive(serde::Serialize)]
+struct EvaluationOutput {
+    root_score: f64,
+    values: BTreeMap<String, f64>,
+}
+
+fn evaluate_values(values_file_path: &str, graph: &ModelGraph) -> Result<()> {
+    let content = fs::read_to_string(values_file_path)
+        .context(format!("读取取值文件{:?}错误", values_file_path))?;
+    let values: HashMap<String, f64> = serde_json::from_str(&content)
+        .context(format!("解析取值文件{:?}错误", values_file_path))?;
+    let leaf_values = values.iter().map(|(name, value)| Ok((resolve(name, graph.models())?, *value)))
+        .collect::<Result<HashMap<ModelId, f64>>>()?;
+    let evaluation = graph.evaluate(&leaf_values, None)?;
+    let output = EvaluationOutput {
+        root_score: evaluation.root_score,
+        values: evaluation.values.iter().map(|(id, value)| (output_key(*id, graph.models()), *value)).collect(),
+    };
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+// 每个修改的模型一行，子节点或算法未改变时不输出
+fn print_import_report(report: &ImportReport) {
+    if !report.added.is_empty() {
+        println!("新增{}个模型：{}", report.added.len(), report.added.join("、"));
+    }
+    if !report.removed.is_empty() {
+        println!("删除{}个模型：{}", report.removed.len(), report.removed.join("、"));
+    }
+    for diff in &report.changed {
+        let mut changes = vec![];
+        if diff.old_children != diff.new_children {
+            let names = |children: &Option<Vec<String>>| children.as_ref().map_or("无".to_string(), |children| format!("[{}]", children.join("、")));
+            changes.push(format!("子节点{} → {}", names(&diff.old_children), names(&diff.new_children)));
+        }
+        if diff.old_algorithm != diff.new_algorithm {
+            let algorithm = |algorithm: &Option<String>| algorithm.clone().unwrap_or_else(|| "无".to_string());
+            changes.push(format!("算法{} → {}", algorithm(&diff.old_algorithm), algorithm(&diff.new_algorithm)));
+        }
+        println!("修改{}：{}", diff.name, changes.join("；"));
+    }
+}
@@ -0,0 +1,7 @@
+// 命令行模式的控制台程序，子命令见cli.rs
+// 与"app --cli"相同，但在Windows的发布版本中也能输出结果和退出码
+mod cli;
+
+fn main() {
+    std::process::exit(cli::run(std::env::args().skip(1).collect()));
+}
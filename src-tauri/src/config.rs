@@ -0,0 +1,85 @@
+// 启动配置：模型路径与根节点
+// 优先级依次为命令行参数、环境变量、配置文件、默认值，未指定根节点时由模型自动推断
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_MODELS_FILE_PATH: &str = "../models/model.json";
+// 相对于当前目录
+pub const SETTINGS_FILE_PATH: &str = "settings.json";
+pub const MODELS_FILE_PATH_VAR: &str = "MODELS_FILE_PATH";
+pub const ROOT_NAME_VAR: &str = "MODELS_ROOT_NAME";
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct Settings {
+    // 模型文件或旧版的模型目录
+    pub models_file_path: Option<String>,
+    pub root_name: Option<String>,
+}
+
+impl Settings {
+    // self中未指定的项由other补全
+    fn or(self, other: Settings) -> Settings {
+        Settings {
+            models_file_path: self.models_file_path.or(other.models_file_path),
+            root_name: self.root_name.or(other.root_name),
+        }
+    }
+}
+
+// 支持"--models <路径>"、"--root <名称>"以及"--models=<路径>"的写法
+pub fn from_args(args: impl Iterator<Item = String>) -> Result<Settings> {
+    let mut settings = Settings::default();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        let (key, value) = match arg.split_once('=') {
+            Some((key, value)) => (key.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        let target = match key.as_str() {
+            "--models" => &mut settings.models_file_path,
+            "--root" => &mut settings.root_name,
+            _ => {
+                // 打包后的程序可能由系统传入其他参数
+                eprintln!("忽略未知的命令行参数{:?}", arg);
+                continue;
+            }
+        };
+        let value = match value {
+            Some(value) => value,
+            None => args.next().context(format!("命令行参数{}缺少取值", key))?,
+        };
+        *target = Some(value);
+    }
+    Ok(settings)
+}
+
+pub fn from_env() -> Settings {
+    Settings {
+        models_file_path: std::env::var(MODELS_FILE_PATH_VAR).ok(),
+        root_name: std::env::var(ROOT_NAME_VAR).ok(),
+    }
+}
+
+// 配置文件不存在时视为未指定
+pub fn from_file(file_path: &str) -> Result<Settings> {
+    if !Path::new(file_path).exists() {
+        return Ok(Settings::default());
+    }
+    let content = fs::read_to_string(file_path).context(format!("读取配置文件{:?}错误", file_path))?;
+    serde_json::from_str(&content).context(format!("解析配置文件{:?}错误", file_path))
+}
+
+// 返回模型路径和根节点
+pub fn load() -> Result<(String, Option<String>)> {
+    load_from(std::env::args().skip(1))
+}
+
+// args为不含程序名的命令行参数
+pub fn load_from(args: impl Iterator<Item = String>) -> Result<(String, Option<String>)> {
+    let settings = from_args(args)?
+        .or(from_env())
+        .or(from_file(SETTINGS_FILE_PATH)?);
+    let models_file_path = settings.models_file_path.unwrap_or_else(|| DEFAULT_MODELS_FILE_PATH.to_string());
+    Ok((models_file_path, settings.root_name))
+}
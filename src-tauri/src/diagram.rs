@@ -0,0 +1,129 @@
+// 将模型图导出为Graphviz DOT或Mermaid图，用于评审时展示层级
+// 被多个父节点引用的模型只画一次，每次引用各画一条边；组合模型的算法写在节点标签中
+use crate::algorithm;
+use crate::error::ModelError;
+use crate::{Model, ModelId};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum DiagramFormat {
+    Dot,
+    Mermaid,
+}
+
+impl DiagramFormat {
+    // 命令行中的写法，不区分大小写
+    pub fn parse(name: &str) -> Option<DiagramFormat> {
+        match name.to_lowercase().as_str() {
+            "dot" => Some(DiagramFormat::Dot),
+            "mermaid" => Some(DiagramFormat::Mermaid),
+            _ => None,
+        }
+    }
+}
+
+struct DiagramNode<'a> {
+    model: &'a Model,
+    // 有子节点但因超过depth未展开
+    truncated: bool,
+}
+
+struct Diagram<'a> {
+    nodes: Vec<DiagramNode<'a>>,
+    // (父节点, 子节点)，子节点在父节点中出现几次就有几条
+    edges: Vec<(ModelId, ModelId)>,
+}
+
+// 从root出发广度优先遍历，每个模型在最浅的一层展开，depth为None时不限层数，为0时只有根节点
+fn collect(root_id: ModelId, depth: Option<usize>, models: &HashMap<ModelId, Model>) -> Result<Diagram<'_>, ModelError> {
+    let root = models.get(&root_id).ok_or_else(|| ModelError::not_found(root_id))?;
+    let mut nodes = vec![];
+    let mut edges = vec![];
+    let mut visited = HashSet::new();
+    visited.insert(root_id);
+    let mut queue = VecDeque::new();
+    queue.push_back((root, 0));
+    while let Some((model, level)) = queue.pop_front() {
+        let children = model.children.iter().flatten().collect::<Vec<&ModelId>>();
+        let expanded = depth.map_or(true, |depth| level < depth);
+        nodes.push(DiagramNode { model, truncated: !expanded && !children.is_empty() });
+        if !expanded {
+            continue;
+        }
+        for child in children {
+            let child_model = models.get(child).ok_or_else(|| ModelError::not_found(*child))?;
+            edges.push((model.id, *child));
+            if visited.insert(*child) {
+                queue.push_back((child_model, level + 1));
+            }
+        }
+    }
+    Ok(Diagram { nodes, edges })
+}
+
+// 算法尚未确定时不显示
+fn algorithm_label(model: &Model) -> Option<&str> {
+    model.algorithm.as_deref().filter(|source| !algorithm::is_placeholder(source))
+}
+
+pub fn export_graph(format: DiagramFormat, root_id: ModelId, depth: Option<usize>, models: &HashMap<ModelId, Model>) -> Result<String, ModelError> {
+    let diagram = collect(root_id, depth, models)?;
+    Ok(match format {
+        DiagramFormat::Dot => to_dot(&models[&root_id].name, &diagram),
+        DiagramFormat::Mermaid => to_mermaid(&diagram),
+    })
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn to_dot(name: &str, diagram: &Diagram) -> String {
+    let mut lines = vec![format!("digraph \"{}\" {{", dot_escape(name)), "    node [shape=box];".to_string()];
+    for node in &diagram.nodes {
+        let mut label = dot_escape(&node.model.name);
+        if let Some(source) = algorithm_label(node.model) {
+            label = format!("{}\\n{}", label, dot_escape(source));
+        }
+        let mut attributes = vec![format!("label=\"{}\"", label)];
+        // 共享的模型画双线边框，未展开的画虚线
+        if node.model.ref_count > 1 {
+            attributes.push("peripheries=2".to_string());
+        }
+        if node.truncated {
+            attributes.push("style=dashed".to_string());
+        }
+        lines.push(format!("    n{} [{}];", node.model.id, attributes.join(", ")));
+    }
+    lines.extend(diagram.edges.iter().map(|(parent, child)| format!("    n{} -> n{};", parent, child)));
+    lines.push("}".to_string());
+    lines.join("\n") + "\n"
+}
+
+// Mermaid的标签中"、<、>和#需写作实体
+fn mermaid_escape(text: &str) -> String {
+    text.replace('#', "#35;").replace('"', "#quot;").replace('<', "#lt;").replace('>', "#gt;").replace('\n', "<br/>")
+}
+
+fn to_mermaid(diagram: &Diagram) -> String {
+    let mut lines = vec!["flowchart TD".to_string()];
+    for node in &diagram.nodes {
+        let mut label = mermaid_escape(&node.model.name);
+        if let Some(source) = algorithm_label(node.model) {
+            label = format!("{}<br/>{}", label, mermaid_escape(source));
+        }
+        lines.push(format!("    n{}[\"{}\"]", node.model.id, label));
+    }
+    lines.extend(diagram.edges.iter().map(|(parent, child)| format!("    n{} --> n{}", parent, child)));
+    let shared = diagram.nodes.iter().filter(|node| node.model.ref_count > 1).map(|node| format!("n{}", node.model.id)).collect::<Vec<String>>();
+    if !shared.is_empty() {
+        lines.push("    classDef shared stroke-width:3px".to_string());
+        lines.push(format!("    class {} shared", shared.join(",")));
+    }
+    let truncated = diagram.nodes.iter().filter(|node| node.truncated).map(|node| format!("n{}", node.model.id)).collect::<Vec<String>>();
+    if !truncated.is_empty() {
+        lines.push("    classDef truncated stroke-dasharray:5 5".to_string());
+        lines.push(format!("    class {} truncated", truncated.join(",")));
+    }
+    lines.join("\n") + "\n"
+}
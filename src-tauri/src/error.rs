@@ -0,0 +1,124 @@
+// 命令返回给前端的错误，前端根据kind决定如何恢复，message可以直接展示给用户
+use crate::algorithm::AlgorithmError;
+use crate::{model_label, Model, ModelId};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind")]
+pub enum ModelError {
+    // 未找到模型，通常是前后端失去同步
+    NotFound { id: ModelId, message: String },
+    // 模型没有子节点，不能进行需要子节点的操作
+    NotAComposite { id: ModelId, name: String, message: String },
+    // 模型的状态违反了约定，例如有子节点但没有算法
+    InvariantViolation { id: ModelId, name: String, message: String },
+    // 要求名称唯一时，名称已被id对应的模型占用
+    NameConflict { id: ModelId, name: String, message: String },
+    // 算法无法解析、类型错误、引用了不存在的子节点或无法计算
+    InvalidAlgorithm { id: ModelId, name: String, message: String },
+    // 修改会使模型成为自己的祖先，path的首尾是同一个模型
+    CycleDetected { path: Vec<ModelId>, message: String },
+    // 计算时缺少叶节点的值
+    MissingValues { ids: Vec<ModelId>, message: String },
+    // 查询条件无效，例如无法解析的正则表达式
+    InvalidQuery { message: String },
+    // 导入的表格、大纲或读取的模型文件格式有误，line为出错的行号，从1开始；整体的问题（如循环引用）没有行号
+    InvalidImport { line: Option<usize>, message: String },
+    // 无法确定根节点：指定的根节点不存在或重名，或没有父节点的模型不止一个；ids为可选的根节点
+    InvalidRoot { ids: Vec<ModelId>, message: String },
+    // 没有可以撤销或重做的修改
+    EmptyHistory { message: String },
+    // 日志中有上次未保存的修改，需先决定恢复或丢弃
+    PendingJournal { entries: usize, message: String },
+    // 读写文件错误
+    Io { message: String },
+}
+
+impl ModelError {
+    pub fn not_found(id: ModelId) -> Self {
+        ModelError::NotFound { id, message: format!("未找到ID为{}的模型", id) }
+    }
+
+    pub fn not_a_composite(model: &Model) -> Self {
+        ModelError::NotAComposite { id: model.id, name: model.name.clone(), message: format!("模型{}无子节点", model.name) }
+    }
+
+    pub fn invariant_violation(model: &Model, message: String) -> Self {
+        ModelError::InvariantViolation { id: model.id, name: model.name.clone(), message }
+    }
+
+    pub fn name_conflict(model: &Model) -> Self {
+        ModelError::NameConflict { id: model.id, name: model.name.clone(), message: format!("模型{}已存在", model.name) }
+    }
+
+    pub fn invalid_algorithm(model: &Model, error: AlgorithmError) -> Self {
+        ModelError::InvalidAlgorithm { id: model.id, name: model.name.clone(), message: format!("模型{}的算法有误：{}", model.name, error) }
+    }
+
+    pub fn cycle_detected(path: Vec<ModelId>, models: &HashMap<ModelId, Model>) -> Self {
+        let names = path.iter().map(|id| model_label(*id, models)).collect::<Vec<String>>();
+        let message = format!("模型之间存在循环引用：{}", names.join(" → "));
+        ModelError::CycleDetected { path, message }
+    }
+
+    pub fn missing_values(ids: Vec<ModelId>, models: &HashMap<ModelId, Model>) -> Self {
+        let names = ids.iter().map(|id| model_label(*id, models)).collect::<Vec<String>>();
+        let message = format!("缺少{}个叶节点的值：{}", ids.len(), names.join("、"));
+        ModelError::MissingValues { ids, message }
+    }
+
+    pub fn invalid_query(message: String) -> Self {
+        ModelError::InvalidQuery { message }
+    }
+
+    pub fn invalid_import(line: Option<usize>, message: String) -> Self {
+        let message = match line {
+            Some(line) => format!("第{}行：{}", line, message),
+            None => message,
+        };
+        ModelError::InvalidImport { line, message }
+    }
+
+    pub fn invalid_root(ids: Vec<ModelId>, message: String) -> Self {
+        ModelError::InvalidRoot { ids, message }
+    }
+
+    pub fn empty_history(action: &str) -> Self {
+        ModelError::EmptyHistory { message: format!("没有可以{}的修改", action) }
+    }
+
+    pub fn pending_journal(entries: usize) -> Self {
+        ModelError::PendingJournal { entries, message: format!("日志中有{}条上次未保存的修改，请先恢复或丢弃", entries) }
+    }
+
+    pub fn io(error: anyhow::Error) -> Self {
+        ModelError::Io { message: format!("{:#}", error) }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ModelError::NotFound { message, .. }
+            | ModelError::NotAComposite { message, .. }
+            | ModelError::InvariantViolation { message, .. }
+            | ModelError::NameConflict { message, .. }
+            | ModelError::InvalidAlgorithm { message, .. }
+            | ModelError::CycleDetected { message, .. }
+            | ModelError::MissingValues { message, .. }
+            | ModelError::InvalidQuery { message }
+            | ModelError::InvalidImport { message, .. }
+            | ModelError::InvalidRoot { message, .. }
+            | ModelError::EmptyHistory { message }
+            | ModelError::PendingJournal { message, .. }
+            | ModelError::Io { message } => message,
+        }
+    }
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for ModelError {}
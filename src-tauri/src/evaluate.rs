@@ -0,0 +1,151 @@
+// 根据叶节点的测量值自下而上计算各组合模型的值
+use crate::algorithm::{self, AlgorithmError};
+use crate::error::ModelError;
+use crate::graph::topological_order;
+use crate::{children_labels, Model, ModelId};
+use std::collections::HashMap;
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Evaluation {
+    // 从根节点可达的所有模型的值，包括叶节点
+    pub values: HashMap<ModelId, f64>,
+    pub root_score: f64,
+}
+
+pub fn evaluate_tree(root_id: ModelId, models: &HashMap<ModelId, Model>, leaf_values: &HashMap<ModelId, f64>) -> Result<Evaluation, ModelError> {
+    // 子节点排在父节点之前，被多个父节点引用的模型只计算一次
+    let order = topological_order(root_id, models)?;
+    let missing = order.iter().filter(|id| {
+        models[id].children.is_none() && !leaf_values.contains_key(id)
+    }).copied().collect::<Vec<ModelId>>();
+    if !missing.is_empty() {
+        return Err(ModelError::missing_values(missing, models));
+    }
+    let mut values = HashMap::new();
+    for id in order {
+        let model = &models[&id];
+        let value = match (&model.children, &model.algorithm) {
+            (None, _) => leaf_values[&id],
+            (Some(children), Some(source)) => {
+                if algorithm::is_placeholder(source) {
+                    return Err(ModelError::invalid_algorithm(model, AlgorithmError::new("算法尚未确定".to_string())));
+                }
+                // 算法以名称引用子节点
+                let labels = children_labels(model, models)?;
+                let child_values = children.iter().zip(&labels).map(|(child, label)| (label.as_str(), values[child]))
+                    .collect::<HashMap<&str, f64>>();
+                let expr = algorithm::compile(source, &labels).map_err(|e| ModelError::invalid_algorithm(model, e))?;
+                algorithm::evaluate(&expr, &|name| child_values.get(name).copied()).map_err(|e| ModelError::invalid_algorithm(model, e))?
+            }
+            (Some(_), None) => {
+                return Err(ModelError::invariant_violation(model, format!("模型{}状态冲突：有子节点但无算法声明", model.name)));
+            }
+        };
+        values.insert(id, value);
+    }
+    let root_score = values[&root_id];
+    Ok(Evaluation { values, root_score })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::FileModel;
+    use crate::{build_models, find_by_name};
+    use std::collections::HashSet;
+
+    fn composite(name: &str, children: &[&str], algorithm: &str) -> FileModel {
+        FileModel {
+            name: name.to_string(),
+            children: Some(children.iter().map(|child| child.to_string()).collect()),
+            algorithm: Some(algorithm.to_string()),
+            pinned: false,
+        }
+    }
+
+    // A = B + C，B和C共享组合模型D，叶节点为x、y、E
+    fn sample() -> HashMap<ModelId, Model> {
+        build_models(vec![
+            composite("A", &["B", "C"], "B + C"),
+            composite("B", &["D"], "D * 2"),
+            composite("C", &["D", "E"], "D + E"),
+            composite("D", &["x", "y"], "x * y"),
+        ]).unwrap().models
+    }
+
+    fn id(name: &str, models: &HashMap<ModelId, Model>) -> ModelId {
+        find_by_name(name, models)[0]
+    }
+
+    fn leaf_values(values: &[(&str, f64)], models: &HashMap<ModelId, Model>) -> HashMap<ModelId, f64> {
+        values.iter().map(|(name, value)| (id(name, models), *value)).collect()
+    }
+
+    #[test]
+    fn shared_child_is_computed_once() {
+        let models = sample();
+        let (a, d) = (id("A", &models), id("D", &models));
+        // 计算按拓扑顺序进行，共享的D只出现一次，B和C使用同一个值
+        let order = topological_order(a, &models).unwrap();
+        assert_eq!(order.iter().filter(|model| **model == d).count(), 1);
+        assert!(order.iter().position(|model| *model == d) < order.iter().position(|model| *model == id("B", &models)));
+        let evaluation = evaluate_tree(a, &models, &leaf_values(&[("x", 2.0), ("y", 3.0), ("E", 1.0)], &models)).unwrap();
+        assert_eq!(evaluation.values[&d], 6.0);
+        assert_eq!(evaluation.values[&id("B", &models)], 12.0);
+        assert_eq!(evaluation.values[&id("C", &models)], 7.0);
+        assert_eq!(evaluation.root_score, 19.0);
+        assert_eq!(evaluation.values.len(), models.len());
+    }
+
+    #[test]
+    fn subtree_needs_only_its_own_leaves() {
+        let models = sample();
+        let evaluation = evaluate_tree(id("D", &models), &models, &leaf_values(&[("x", 2.0), ("y", 3.0)], &models)).unwrap();
+        assert_eq!(evaluation.root_score, 6.0);
+        assert_eq!(evaluation.values.len(), 3);
+    }
+
+    #[test]
+    fn missing_leaf_value_names_the_leaf() {
+        let models = sample();
+        let error = evaluate_tree(id("A", &models), &models, &leaf_values(&[("x", 2.0)], &models)).unwrap_err();
+        let (y, e) = (id("y", &models), id("E", &models));
+        match &error {
+            ModelError::MissingValues { ids, .. } => assert_eq!(ids.iter().copied().collect::<HashSet<ModelId>>(), HashSet::from([y, e])),
+            _ => panic!("应为MissingValues：{:?}", error),
+        }
+        assert!(error.message().contains('y') && error.message().contains('E'));
+    }
+
+    #[test]
+    fn placeholder_algorithm_is_an_error() {
+        let mut models = sample();
+        let b = id("B", &models);
+        models.get_mut(&b).unwrap().algorithm = Some("待定".to_string());
+        let error = evaluate_tree(id("A", &models), &models, &leaf_values(&[("x", 2.0), ("y", 3.0), ("E", 1.0)], &models)).unwrap_err();
+        assert!(matches!(&error, ModelError::InvalidAlgorithm { id, .. } if *id == b), "{:?}", error);
+        assert!(error.message().contains("算法尚未确定"));
+    }
+
+    #[test]
+    fn cycle_is_an_error() {
+        let mut models = sample();
+        let (a, d, x) = (id("A", &models), id("D", &models), id("x", &models));
+        // build_models会拒绝有环的模型，这里直接修改
+        let model = models.get_mut(&d).unwrap();
+        model.children = Some(vec![x, a]);
+        model.algorithm = Some("x + A".to_string());
+        let error = evaluate_tree(a, &models, &leaf_values(&[("x", 2.0), ("y", 3.0), ("E", 1.0)], &models)).unwrap_err();
+        assert!(matches!(error, ModelError::CycleDetected { .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn division_by_zero_names_the_model() {
+        let mut models = sample();
+        let c = id("C", &models);
+        models.get_mut(&c).unwrap().algorithm = Some("D / E".to_string());
+        let error = evaluate_tree(id("A", &models), &models, &leaf_values(&[("x", 2.0), ("y", 3.0), ("E", 0.0)], &models)).unwrap_err();
+        assert!(matches!(&error, ModelError::InvalidAlgorithm { name, .. } if name == "C"), "{:?}", error);
+        assert!(error.message().contains("除数为0"));
+    }
+}
@@ -0,0 +1,109 @@
+// 推送给前端的修改事件：修改模型的命令结束后按修改的内容分别发出事件，每个事件只列出确实改变了的模型
+// 前端的每个节点据此只刷新自己，不需要重新查询整棵子树
+use crate::{Model, ModelGraph, ModelId};
+use std::collections::BTreeSet;
+
+pub const NODE_RENAMED: &str = "node-renamed";
+pub const CHILDREN_CHANGED: &str = "children-changed";
+pub const REF_COUNT_CHANGED: &str = "ref-count-changed";
+pub const ALGORITHM_CHANGED: &str = "algorithm-changed";
+pub const PINNED_CHANGED: &str = "pinned-changed";
+
+// 一次命令中名称、子节点、算法或入口设置改变了的模型，引用计数的改变由ParentIndex记录
+#[derive(Debug, Clone, Default)]
+pub struct ChangeSet {
+    renamed: BTreeSet<ModelId>,
+    children_changed: BTreeSet<ModelId>,
+    algorithm_changed: BTreeSet<ModelId>,
+    pinned_changed: BTreeSet<ModelId>,
+    // 新加入的模型，前端还没有显示，不需要发出事件
+    created: BTreeSet<ModelId>,
+}
+
+impl ChangeSet {
+    // 新加入或被移除的模型由其父节点的children-changed事件体现
+    pub fn record(&mut self, before: Option<&Model>, after: Option<&Model>) {
+        if let (None, Some(after)) = (before, after) {
+            self.created.insert(after.id);
+        }
+        if let (Some(before), Some(after)) = (before, after) {
+            if before.name != after.name {
+                self.renamed.insert(after.id);
+            }
+            if before.children != after.children {
+                self.children_changed.insert(after.id);
+            }
+            if before.algorithm != after.algorithm {
+                self.algorithm_changed.insert(after.id);
+            }
+            if before.pinned != after.pinned {
+                self.pinned_changed.insert(after.id);
+            }
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct RenamedModel {
+    pub id: ModelId,
+    pub name: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct ChildrenChangedModel {
+    pub id: ModelId,
+    // None表示模型不再有子节点
+    pub children: Option<Vec<ModelId>>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct RefCountChangedModel {
+    pub id: ModelId,
+    pub ref_count: u64,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct AlgorithmChangedModel {
+    pub id: ModelId,
+    pub algorithm: Option<String>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct PinnedChangedModel {
+    pub id: ModelId,
+    pub pinned: bool,
+}
+
+// 每个事件的内容，按ID排序
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct ChangeEvent<T> {
+    pub models: Vec<T>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeEvents {
+    pub renamed: Vec<RenamedModel>,
+    pub children_changed: Vec<ChildrenChangedModel>,
+    pub ref_count_changed: Vec<RefCountChangedModel>,
+    pub algorithm_changed: Vec<AlgorithmChangedModel>,
+    pub pinned_changed: Vec<PinnedChangedModel>,
+}
+
+// 取出graph中累积的修改，转为各事件的内容；新加入的和已被移除的模型不包括在内
+pub fn take_events(graph: &mut ModelGraph) -> ChangeEvents {
+    let changes = std::mem::take(&mut graph.changes);
+    let models = &graph.models;
+    ChangeEvents {
+        renamed: changes.renamed.iter().filter_map(|id| models.get(id))
+            .map(|model| RenamedModel { id: model.id, name: model.name.clone() }).collect(),
+        children_changed: changes.children_changed.iter().filter_map(|id| models.get(id))
+            .map(|model| ChildrenChangedModel { id: model.id, children: model.children.clone() }).collect(),
+        ref_count_changed: graph.parents.take_changed(models).into_iter()
+            .filter(|(id, _)| !changes.created.contains(id))
+            .map(|(id, ref_count)| RefCountChangedModel { id, ref_count }).collect(),
+        algorithm_changed: changes.algorithm_changed.iter().filter_map(|id| models.get(id))
+            .map(|model| AlgorithmChangedModel { id: model.id, algorithm: model.algorithm.clone() }).collect(),
+        pinned_changed: changes.pinned_changed.iter().filter_map(|id| models.get(id))
+            .map(|model| PinnedChangedModel { id: model.id, pinned: model.pinned }).collect(),
+    }
+}
@@ -0,0 +1,266 @@
+// 扁平格式的导入与导出，供在表格或文本中维护层级：与版本1的模型文件一样以名称引用模型
+// 边表：CSV，每行一条父子关系（parent, child, position, parent_algorithm），带BOM以便Excel识别编码
+// 大纲：每行一个模型，以缩进表示层级，"名称 = 算法"；共享的模型只在第一次出现时展开
+use crate::error::ModelError;
+use crate::schema::FileModel;
+use anyhow::Context;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FlatFormat {
+    Csv,
+    Outline,
+}
+
+impl FlatFormat {
+    // 按扩展名判断，.csv为边表，.txt为大纲
+    pub fn from_path(path: &str) -> Option<FlatFormat> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(FlatFormat::Csv),
+            "txt" => Some(FlatFormat::Outline),
+            _ => None,
+        }
+    }
+}
+
+const BOM: char = '\u{feff}';
+const CSV_HEADER: [&str; 4] = ["parent", "child", "position", "parent_algorithm"];
+const OUTLINE_INDENT: &str = "  ";
+const OUTLINE_SEPARATOR: &str = " = ";
+
+pub fn render(format: FlatFormat, file_models: &[FileModel]) -> Result<String, ModelError> {
+    match format {
+        FlatFormat::Csv => to_csv(file_models),
+        FlatFormat::Outline => Ok(to_outline(file_models)),
+    }
+}
+
+pub fn parse(format: FlatFormat, content: &str) -> Result<Vec<FileModel>, ModelError> {
+    // Excel保存的UTF-8文件以BOM开头
+    let content = content.strip_prefix(BOM).unwrap_or(content);
+    match format {
+        FlatFormat::Csv => from_csv(content),
+        FlatFormat::Outline => from_outline(content),
+    }
+}
+
+pub fn read_file(format: FlatFormat, file_path: &str) -> Result<Vec<FileModel>, ModelError> {
+    let content = fs::read_to_string(file_path).context(format!("读取文件{:?}错误", file_path)).map_err(ModelError::io)?;
+    parse(format, &content)
+}
+
+pub fn write_file(format: FlatFormat, file_path: &str, file_models: &[FileModel]) -> Result<(), ModelError> {
+    let content = render(format, file_models)?;
+    fs::write(file_path, content).context(format!("写入文件{:?}错误", file_path)).map_err(ModelError::io)
+}
+
+// 没有父节点的模型，按file_models中的顺序
+fn top_level(file_models: &[FileModel]) -> Vec<&FileModel> {
+    let children = file_models.iter().flat_map(|model| model.children.iter().flatten()).collect::<HashSet<&String>>();
+    file_models.iter().filter(|model| !children.contains(&model.name)).collect()
+}
+
+// 位置从1开始，与表格中的行号习惯一致
+// 没有子节点的组合模型写一行空的child；不被引用的叶节点写一行空的child和算法，否则导入时会丢失
+fn to_csv(file_models: &[FileModel]) -> Result<String, ModelError> {
+    let top_level = top_level(file_models).into_iter().map(|model| model.name.as_str()).collect::<HashSet<&str>>();
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let mut write = |record: [&str; 4]| writer.write_record(record).map_err(|e| ModelError::io(e.into()));
+    write(CSV_HEADER)?;
+    for model in file_models {
+        let algorithm = model.algorithm.as_deref().unwrap_or_default();
+        match &model.children {
+            Some(children) if !children.is_empty() => {
+                for (index, child) in children.iter().enumerate() {
+                    write([&model.name, child, &(index + 1).to_string(), algorithm])?;
+                }
+            }
+            Some(_) => write([&model.name, "", "", algorithm])?,
+            None if top_level.contains(model.name.as_str()) => write([&model.name, "", "", ""])?,
+            None => {}
+        }
+    }
+    let content = writer.into_inner().map_err(|e| ModelError::io(anyhow::anyhow!("{}", e)))?;
+    let content = String::from_utf8(content).map_err(|e| ModelError::io(e.into()))?;
+    Ok(format!("{}{}", BOM, content))
+}
+
+// 导入中一个模型的子节点和算法，children中为(位置, 子节点, 行号)
+#[derive(Default)]
+struct ParsedModel {
+    children: Vec<(Option<usize>, String, usize)>,
+    composite: bool,
+    algorithm: Option<(String, usize)>,
+}
+
+fn from_csv(content: &str) -> Result<Vec<FileModel>, ModelError> {
+    // 单元格的内容原样保留，名称前后的空格也是名称的一部分
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let headers = reader.headers().map_err(|e| ModelError::invalid_import(Some(1), e.to_string()))?.clone();
+    let column = |name: &str| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name));
+    let (parent_column, child_column) = match (column("parent"), column("child")) {
+        (Some(parent), Some(child)) => (parent, child),
+        _ => return Err(ModelError::invalid_import(Some(1), format!("表头需包含{}和{}列", CSV_HEADER[0], CSV_HEADER[1]))),
+    };
+    let (position_column, algorithm_column) = (column("position"), column("parent_algorithm"));
+    let mut order = vec![];
+    let mut parsed: HashMap<String, ParsedModel> = HashMap::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| ModelError::invalid_import(e.position().map(|position| position.line() as usize), e.to_string()))?;
+        let line = record.position().map_or(0, |position| position.line() as usize);
+        let cell = |column: Option<usize>| column.and_then(|column| record.get(column)).unwrap_or_default();
+        let (parent, child) = (cell(Some(parent_column)), cell(Some(child_column)));
+        let (position, algorithm) = (cell(position_column).trim(), cell(algorithm_column));
+        if parent.trim().is_empty() {
+            // 空行或只有逗号的行
+            if record.iter().all(|cell| cell.trim().is_empty()) {
+                continue;
+            }
+            return Err(ModelError::invalid_import(Some(line), "缺少parent".to_string()));
+        }
+        let position = match position {
+            "" => None,
+            position => match position.parse::<usize>() {
+                Ok(position) if position > 0 => Some(position),
+                _ => return Err(ModelError::invalid_import(Some(line), format!("position {:?}不是从1开始的位置", position))),
+            },
+        };
+        if !parsed.contains_key(parent) {
+            order.push(parent.to_string());
+        }
+        let model = parsed.entry(parent.to_string()).or_default();
+        if !child.trim().is_empty() {
+            model.composite = true;
+            model.children.push((position, child.to_string(), line));
+        }
+        if !algorithm.trim().is_empty() {
+            model.composite = true;
+            match &model.algorithm {
+                Some((existing, existing_line)) if existing != algorithm => {
+                    return Err(ModelError::invalid_import(Some(line), format!("模型{}的算法与第{}行不一致", parent, existing_line)));
+                }
+                Some(_) => {}
+                None => model.algorithm = Some((algorithm.to_string(), line)),
+            }
+        }
+    }
+    order.into_iter().map(|name| {
+        let mut model = parsed.remove(&name).unwrap_or_default();
+        // 未写位置的子节点按行的顺序排在最后
+        model.children.sort_by_key(|(position, _, _)| position.unwrap_or(usize::MAX));
+        let mut positions = HashMap::new();
+        for (position, _, line) in &model.children {
+            if let Some(previous) = position.and_then(|position| positions.insert(position, *line)) {
+                return Err(ModelError::invalid_import(Some(*line), format!("模型{}的位置{}与第{}行重复", name, position.unwrap_or_default(), previous)));
+            }
+        }
+        // 有子节点的模型必须有算法，否则导入后无法计算
+        if model.algorithm.is_none() {
+            if let Some((_, _, line)) = model.children.iter().min_by_key(|(_, _, line)| *line) {
+                return Err(ModelError::invalid_import(Some(*line), format!("模型{}有子节点但没有算法，请在{}列中填写", name, CSV_HEADER[3])));
+            }
+        }
+        let children = model.composite.then(|| model.children.into_iter().map(|(_, child, _)| child).collect());
+        Ok(FileModel { name, children, algorithm: model.algorithm.map(|(algorithm, _)| algorithm), pinned: false })
+    }).collect()
+}
+
+fn to_outline(file_models: &[FileModel]) -> String {
+    let by_name = file_models.iter().map(|model| (model.name.as_str(), model)).collect::<HashMap<&str, &FileModel>>();
+    let mut expanded = HashSet::new();
+    let mut lines = vec![];
+    for root in top_level(file_models) {
+        // (名称, 层数)，按先序展开
+        let mut stack = vec![(root.name.as_str(), 0)];
+        while let Some((name, level)) = stack.pop() {
+            let model = by_name.get(name);
+            let mut line = format!("{}{}", OUTLINE_INDENT.repeat(level), name);
+            // 已展开过的模型只写名称，表示引用
+            if let Some(model) = model.filter(|_| expanded.insert(name)) {
+                if let Some(algorithm) = &model.algorithm {
+                    line = format!("{}{}{}", line, OUTLINE_SEPARATOR, algorithm);
+                }
+                stack.extend(model.children.iter().flatten().rev().map(|child| (child.as_str(), level + 1)));
+            }
+            lines.push(line);
+        }
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+// 缩进只需与上下文一致，不要求固定的宽度；有子行或算法的行是模型的定义，每个模型只能定义一次
+fn from_outline(content: &str) -> Result<Vec<FileModel>, ModelError> {
+    let mut order = vec![];
+    let mut parsed: HashMap<String, ParsedModel> = HashMap::new();
+    // 定义各模型的行号
+    let mut definitions: HashMap<String, usize> = HashMap::new();
+    // 当前行的各层祖先：(缩进宽度, 名称, 行号)
+    let mut ancestors: Vec<(usize, String, usize)> = vec![];
+    for (index, text) in content.lines().enumerate() {
+        let line = index + 1;
+        let trimmed = text.trim_start();
+        if trimmed.trim().is_empty() {
+            continue;
+        }
+        let indent = text.len() - trimmed.len();
+        // 名称末尾的空格原样保留，与导出时一致
+        let (name, algorithm) = match trimmed.split_once(OUTLINE_SEPARATOR) {
+            Some((name, algorithm)) => (name, Some(algorithm)),
+            None => (trimmed, None),
+        };
+        if name.trim().is_empty() {
+            return Err(ModelError::invalid_import(Some(line), "缺少模型名称".to_string()));
+        }
+        // 退回到缩进不超过当前行的祖先，退回时缩进必须与外层的某一行相同
+        let mut closed = None;
+        while ancestors.last().map_or(false, |(width, _, _)| *width >= indent) {
+            closed = ancestors.pop().map(|(width, _, _)| width);
+        }
+        if closed.map_or(false, |width| width != indent) || (ancestors.is_empty() && closed.is_none() && !order.is_empty()) {
+            return Err(ModelError::invalid_import(Some(line), "缩进与之前的行不一致".to_string()));
+        }
+        if !parsed.contains_key(name) {
+            order.push(name.to_string());
+        }
+        if let Some((_, parent, parent_line)) = ancestors.last() {
+            define(parent, *parent_line, &mut definitions)?;
+            let parent = parsed.entry(parent.clone()).or_default();
+            parent.composite = true;
+            parent.children.push((None, name.to_string(), line));
+        }
+        let model = parsed.entry(name.to_string()).or_default();
+        if let Some(algorithm) = algorithm {
+            model.composite = true;
+            model.algorithm = Some((algorithm.to_string(), line));
+            define(name, line, &mut definitions)?;
+        }
+        ancestors.push((indent, name.to_string(), line));
+    }
+    order.into_iter().map(|name| {
+        let model = parsed.remove(&name).unwrap_or_default();
+        // 有子行的模型必须写出算法，只有子行时行号为定义该模型的行
+        if model.composite && model.algorithm.is_none() {
+            let line = definitions.get(&name).copied();
+            return Err(ModelError::invalid_import(line, format!("模型{}有子节点但没有算法，请写作\"{}{}<算法>\"", name, name, OUTLINE_SEPARATOR)));
+        }
+        let children = model.composite.then(|| model.children.into_iter().map(|(_, child, _)| child).collect());
+        Ok(FileModel { name, children, algorithm: model.algorithm.map(|(algorithm, _)| algorithm), pinned: false })
+    }).collect()
+}
+
+// 记录name在line定义，同一个模型在另一行再次定义时出错
+fn define(name: &str, line: usize, definitions: &mut HashMap<String, usize>) -> Result<(), ModelError> {
+    match definitions.get(name) {
+        Some(previous) if *previous != line => {
+            Err(ModelError::invalid_import(Some(line), format!("模型{}已在第{}行定义，其他位置只能写名称", name, previous)))
+        }
+        Some(_) => Ok(()),
+        None => {
+            definitions.insert(name.to_string(), line);
+            Ok(())
+        }
+    }
+}
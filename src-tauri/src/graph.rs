@@ -0,0 +1,180 @@
+// 模型图的遍历：children构成有向无环图，被多个父节点引用的模型只访问一次
+use crate::error::ModelError;
+use crate::index::ParentIndex;
+use crate::{Model, ModelId};
+use std::collections::{HashMap, HashSet};
+
+// 从starts出发深度优先遍历，按后序（子节点在父节点之前）记录模型，遇到环时记录环的路径并跳过
+fn walk(starts: Vec<ModelId>, models: &HashMap<ModelId, Model>, order: &mut Vec<ModelId>, cycles: &mut Vec<Vec<ModelId>>) {
+    let mut visited = HashSet::new();
+    for start in starts {
+        if !visited.insert(start) {
+            continue;
+        }
+        // 当前路径上的模型，用于发现环
+        let mut on_path = HashSet::new();
+        on_path.insert(start);
+        // (模型ID, 下一个要访问的子节点下标)
+        let mut stack: Vec<(ModelId, usize)> = vec![(start, 0)];
+        while let Some((id, index)) = stack.last_mut() {
+            let children = models.get(id).and_then(|model| model.children.as_ref());
+            match children.and_then(|children| children.get(*index)).copied() {
+                Some(child) => {
+                    *index += 1;
+                    if on_path.contains(&child) {
+                        let begin = stack.iter().position(|(id, _)| *id == child).unwrap_or(0);
+                        let mut path = stack[begin..].iter().map(|(id, _)| *id).collect::<Vec<ModelId>>();
+                        path.push(child);
+                        cycles.push(path);
+                    } else if visited.insert(child) {
+                        on_path.insert(child);
+                        stack.push((child, 0));
+                    }
+                }
+                None => {
+                    let id = *id;
+                    on_path.remove(&id);
+                    order.push(id);
+                    stack.pop();
+                }
+            }
+        }
+    }
+}
+
+// 从root出发可达的所有模型，子节点排在父节点之前
+pub fn topological_order(root_id: ModelId, models: &HashMap<ModelId, Model>) -> Result<Vec<ModelId>, ModelError> {
+    if !models.contains_key(&root_id) {
+        return Err(ModelError::not_found(root_id));
+    }
+    let mut order = Vec::new();
+    let mut cycles = Vec::new();
+    walk(vec![root_id], models, &mut order, &mut cycles);
+    match cycles.into_iter().next() {
+        Some(path) => Err(ModelError::cycle_detected(path, models)),
+        None => Ok(order),
+    }
+}
+
+// 检查整个模型图，返回所有环的路径，路径的首尾是同一个模型
+pub fn validate_graph(models: &HashMap<ModelId, Model>) -> Vec<Vec<ModelId>> {
+    let mut ids = models.keys().copied().collect::<Vec<ModelId>>();
+    ids.sort_unstable();
+    let mut order = Vec::new();
+    let mut cycles = Vec::new();
+    walk(ids, models, &mut order, &mut cycles);
+    cycles
+}
+
+// 沿children从from到to的一条路径，包括首尾
+pub fn find_path(from: ModelId, to: ModelId, models: &HashMap<ModelId, Model>) -> Option<Vec<ModelId>> {
+    let mut previous: HashMap<ModelId, ModelId> = HashMap::new();
+    let mut stack = vec![from];
+    let mut visited = HashSet::new();
+    visited.insert(from);
+    while let Some(id) = stack.pop() {
+        if id == to {
+            let mut path = vec![to];
+            let mut current = to;
+            while let Some(parent) = previous.get(&current) {
+                path.push(*parent);
+                current = *parent;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        let children = models.get(&id).and_then(|model| model.children.as_ref());
+        children.into_iter().flatten().for_each(|child| {
+            if visited.insert(*child) {
+                previous.insert(*child, id);
+                stack.push(*child);
+            }
+        });
+    }
+    None
+}
+
+// 从root出发广度优先遍历，返回每个可达模型在某条最短路径上的父节点，root本身不在其中
+pub fn shortest_path_parents(root_id: ModelId, models: &HashMap<ModelId, Model>) -> HashMap<ModelId, ModelId> {
+    let mut previous = HashMap::new();
+    let mut queue = std::collections::VecDeque::from(vec![root_id]);
+    while let Some(id) = queue.pop_front() {
+        let children = models.get(&id).and_then(|model| model.children.as_ref());
+        children.into_iter().flatten().for_each(|child| {
+            if *child != root_id && !previous.contains_key(child) {
+                previous.insert(*child, id);
+                queue.push_back(*child);
+            }
+        });
+    }
+    previous
+}
+
+// 在parent下加入child后形成的环，不形成环时返回None
+pub fn cycle_through(parent: ModelId, child: ModelId, models: &HashMap<ModelId, Model>) -> Option<Vec<ModelId>> {
+    let mut path = find_path(child, parent, models)?;
+    path.insert(0, parent);
+    Some(path)
+}
+
+// 从没有父节点的模型到id的所有路径，沿反向索引向上查找，路径数达到limit时截断
+// 返回的路径从顶层模型开始、以id结束，第二个值表示是否被截断
+pub fn usage_paths(id: ModelId, parents: &ParentIndex, limit: usize) -> (Vec<Vec<ModelId>>, bool) {
+    let mut paths = Vec::new();
+    // 从id向上的当前路径，以及路径上每个模型的父节点和下一个要访问的父节点下标
+    let mut path = vec![id];
+    let mut stack: Vec<(Vec<ModelId>, usize)> = vec![(parents.parents(id), 0)];
+    while let Some((current_parents, index)) = stack.last_mut() {
+        if current_parents.is_empty() {
+            if paths.len() == limit {
+                return (paths, true);
+            }
+            paths.push(path.iter().rev().copied().collect());
+        }
+        match current_parents.get(*index).copied() {
+            Some(parent) => {
+                *index += 1;
+                // 模型图无环，正常情况下不会遇到
+                if path.contains(&parent) {
+                    continue;
+                }
+                path.push(parent);
+                stack.push((parents.parents(parent), 0));
+            }
+            None => {
+                stack.pop();
+                path.pop();
+            }
+        }
+    }
+    (paths, false)
+}
+
+// 从某个父节点中删除一次id后不再被任何模型引用的模型，包括id本身及其下的模型
+// 被设为入口的模型和当前的根节点不会被删除；按从上到下的顺序返回
+pub fn orphans_after_removal(id: ModelId, root_id: ModelId, models: &HashMap<ModelId, Model>) -> Vec<ModelId> {
+    orphans_after_removals(&[id], root_id, models)
+}
+
+// 同时删除多个引用后不再被引用的模型，removed中每出现一次就减少一次引用
+pub fn orphans_after_removals(removed: &[ModelId], root_id: ModelId, models: &HashMap<ModelId, Model>) -> Vec<ModelId> {
+    // 删除过程中引用计数的变化，不修改模型
+    let mut ref_counts: HashMap<ModelId, u64> = HashMap::new();
+    let mut orphans = Vec::new();
+    let mut queue = removed.iter().copied().collect::<std::collections::VecDeque<ModelId>>();
+    while let Some(current) = queue.pop_front() {
+        let model = match models.get(&current) {
+            Some(model) => model,
+            None => continue,
+        };
+        let ref_count = ref_counts.entry(current).or_insert(model.ref_count);
+        *ref_count = ref_count.saturating_sub(1);
+        if *ref_count > 0 || model.pinned || current == root_id {
+            continue;
+        }
+        orphans.push(current);
+        // 子节点在children中出现几次就减少几次引用
+        queue.extend(model.children.iter().flatten().copied());
+    }
+    orphans
+}
@@ -0,0 +1,145 @@
+// 撤销/重做：每次修改前后记录受影响模型的状态，撤销时恢复修改前的状态，重做时恢复修改后的状态
+// 恢复时经过反向索引，引用计数随之更新
+use crate::error::ModelError;
+use crate::events::ChangeSet;
+use crate::graph::cycle_through;
+use crate::index::ParentIndex;
+use crate::{Model, ModelGraph, ModelId};
+use std::collections::HashMap;
+
+// 最多保留的撤销步数
+const MAX_HISTORY: usize = 200;
+
+#[derive(Debug, Clone)]
+struct ModelChange {
+    id: ModelId,
+    // None表示修改前（后）不存在该模型
+    before: Option<Model>,
+    after: Option<Model>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Edit {
+    description: String,
+    changes: Vec<ModelChange>,
+}
+
+impl Edit {
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    // 将这次修改记入changes，undone为true时记录的是撤销这次修改带来的改变
+    pub fn record_changes(&self, changes: &mut ChangeSet, undone: bool) {
+        self.changes.iter().for_each(|change| {
+            let (before, after) = (change.before.as_ref(), change.after.as_ref());
+            if undone {
+                changes.record(after, before);
+            } else {
+                changes.record(before, after);
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+}
+
+impl History {
+    fn record(&mut self, edit: Edit) {
+        if edit.changes.is_empty() {
+            return;
+        }
+        self.undo_stack.push(edit);
+        if self.undo_stack.len() > MAX_HISTORY {
+            self.undo_stack.remove(0);
+        }
+        // 新的修改使重做记录失效
+        self.redo_stack.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    // 返回被撤销的修改
+    pub fn undo(&mut self, models: &mut HashMap<ModelId, Model>, parents: &mut ParentIndex) -> Option<&Edit> {
+        let edit = self.undo_stack.pop()?;
+        restore(&edit, models, parents, |change| &change.before);
+        self.redo_stack.push(edit);
+        self.redo_stack.last()
+    }
+
+    // 返回被重做的修改
+    pub fn redo(&mut self, models: &mut HashMap<ModelId, Model>, parents: &mut ParentIndex) -> Option<&Edit> {
+        let edit = self.redo_stack.pop()?;
+        restore(&edit, models, parents, |change| &change.after);
+        self.undo_stack.push(edit);
+        self.undo_stack.last()
+    }
+}
+
+fn restore(edit: &Edit, models: &mut HashMap<ModelId, Model>, parents: &mut ParentIndex, select: impl Fn(&ModelChange) -> &Option<Model>) {
+    edit.changes.iter().for_each(|change| match select(change) {
+        Some(model) => {
+            parents.insert(model.clone(), models);
+        }
+        None => {
+            parents.remove(change.id, models);
+        }
+    });
+}
+
+// 修改后新加入的父子关系形成的环
+fn find_new_cycle(changes: &[ModelChange], models: &HashMap<ModelId, Model>) -> Option<Vec<ModelId>> {
+    changes.iter().find_map(|change| {
+        let after = change.after.as_ref()?;
+        let old_children = change.before.as_ref().and_then(|model| model.children.as_ref());
+        after.children.iter().flatten().filter(|child| {
+            !old_children.map_or(false, |children| children.contains(child))
+        }).find_map(|child| cycle_through(after.id, *child, models))
+    })
+}
+
+// 记录一次修改：affected_ids需包含所有可能被修改、加入或移除的模型
+// 修改失败或会形成环时恢复修改前的状态，不记录
+pub fn record_edit<R>(
+    state: &mut ModelGraph,
+    description: String,
+    affected_ids: Vec<ModelId>,
+    edit: impl FnOnce(&mut ModelGraph) -> Result<R, ModelError>,
+) -> Result<R, ModelError> {
+    let mut affected_ids = affected_ids;
+    affected_ids.sort_unstable();
+    affected_ids.dedup();
+    let before = affected_ids.iter().map(|id| state.models.get(id).cloned()).collect::<Vec<Option<Model>>>();
+    let result = edit(state);
+    let changes = affected_ids.into_iter().zip(before).filter_map(|(id, before)| {
+        let after = state.models.get(&id).cloned();
+        if before == after {
+            None
+        } else {
+            Some(ModelChange { id, before, after })
+        }
+    }).collect::<Vec<ModelChange>>();
+    let edit = Edit { description, changes };
+    let result = result.and_then(|result| match find_new_cycle(&edit.changes, &state.models) {
+        Some(path) => Err(ModelError::cycle_detected(path, &state.models)),
+        None => Ok(result),
+    });
+    if result.is_err() {
+        restore(&edit, &mut state.models, &mut state.parents, |change| &change.before);
+        return result;
+    }
+    edit.record_changes(&mut state.changes, false);
+    state.modified |= !edit.changes.is_empty();
+    state.history.record(edit);
+    result
+}
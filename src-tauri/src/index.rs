@@ -0,0 +1,112 @@
+// 反向索引：子节点 → 引用它的父节点，随children的修改增量维护
+// 修改children、加入或移除模型都经过这里，代价只与涉及的父子关系数量有关，不需要遍历所有模型
+// 模型的ref_count是索引的缓存，由索引在修改时一并更新
+use crate::{Model, ModelId};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Default)]
+pub struct ParentIndex {
+    // 子节点 → (父节点 → 子节点在父节点children中出现的次数)
+    parents: HashMap<ModelId, BTreeMap<ModelId, u64>>,
+    // 引用计数被修改过的模型及修改前的值，由take_changed取出
+    changed: HashMap<ModelId, u64>,
+}
+
+impl ParentIndex {
+    // 由所有模型的children建立索引，并据此设置所有模型的引用计数
+    pub fn build(models: &mut HashMap<ModelId, Model>) -> Self {
+        let mut index = ParentIndex::default();
+        models.values().for_each(|model| {
+            model.children.iter().flatten().for_each(|child| index.link(model.id, *child));
+        });
+        models.values_mut().for_each(|model| model.ref_count = index.ref_count(model.id));
+        index
+    }
+
+    // 引用该模型的所有父节点，按ID排序，同一个父节点只出现一次
+    pub fn parents(&self, id: ModelId) -> Vec<ModelId> {
+        self.parents.get(&id).map_or_else(Vec::new, |parents| parents.keys().copied().collect())
+    }
+
+    // 模型在所有父节点的children中出现的总次数
+    pub fn ref_count(&self, id: ModelId) -> u64 {
+        self.parents.get(&id).map_or(0, |parents| parents.values().sum())
+    }
+
+    // 修改模型的children，返回原来的children
+    pub fn set_children(&mut self, id: ModelId, children: Option<Vec<ModelId>>, models: &mut HashMap<ModelId, Model>) -> Option<Vec<ModelId>> {
+        let model = models.get_mut(&id)?;
+        let old = std::mem::replace(&mut model.children, children);
+        let new = model.children.clone();
+        self.relink(id, old.as_deref().unwrap_or_default(), new.as_deref().unwrap_or_default(), models);
+        old
+    }
+
+    // 加入模型，已有同一ID的模型时替换之
+    pub fn insert(&mut self, model: Model, models: &mut HashMap<ModelId, Model>) {
+        let id = model.id;
+        let new = model.children.clone();
+        let old = models.insert(id, model);
+        let old_ref_count = old.as_ref().map(|old| old.ref_count);
+        let old = old.and_then(|old| old.children);
+        self.relink(id, old.as_deref().unwrap_or_default(), new.as_deref().unwrap_or_default(), models);
+        let ref_count = self.ref_count(id);
+        if let Some(model) = models.get_mut(&id) {
+            model.ref_count = ref_count;
+        }
+        // 新加入的模型不算作引用计数改变
+        if let Some(old_ref_count) = old_ref_count.filter(|old_ref_count| *old_ref_count != ref_count) {
+            self.changed.entry(id).or_insert(old_ref_count);
+        }
+    }
+
+    // 移除模型，其子节点的引用计数随之减少；仍引用该模型的父节点需由调用者先行处理
+    pub fn remove(&mut self, id: ModelId, models: &mut HashMap<ModelId, Model>) -> Option<Model> {
+        let model = models.remove(&id)?;
+        self.relink(id, model.children.as_deref().unwrap_or_default(), &[], models);
+        Some(model)
+    }
+
+    // 取出自上次调用以来引用计数改变了的模型及其当前的引用计数，改回原值的和已被移除的模型不包括在内
+    pub fn take_changed(&mut self, models: &HashMap<ModelId, Model>) -> Vec<(ModelId, u64)> {
+        let mut changed = self.changed.drain().filter_map(|(id, old_ref_count)| {
+            let model = models.get(&id)?;
+            if model.ref_count == old_ref_count { None } else { Some((id, model.ref_count)) }
+        }).collect::<Vec<(ModelId, u64)>>();
+        changed.sort_unstable();
+        changed
+    }
+
+    fn link(&mut self, parent: ModelId, child: ModelId) {
+        *self.parents.entry(child).or_default().entry(parent).or_insert(0) += 1;
+    }
+
+    fn unlink(&mut self, parent: ModelId, child: ModelId) {
+        if let Some(parents) = self.parents.get_mut(&child) {
+            if let Some(count) = parents.get_mut(&parent) {
+                *count -= 1;
+                if *count == 0 {
+                    parents.remove(&parent);
+                }
+            }
+            if parents.is_empty() {
+                self.parents.remove(&child);
+            }
+        }
+    }
+
+    // parent的children由old变为new，更新索引及涉及的子节点的引用计数
+    fn relink(&mut self, parent: ModelId, old: &[ModelId], new: &[ModelId], models: &mut HashMap<ModelId, Model>) {
+        old.iter().for_each(|child| self.unlink(parent, *child));
+        new.iter().for_each(|child| self.link(parent, *child));
+        old.iter().chain(new).for_each(|child| {
+            let ref_count = self.ref_count(*child);
+            if let Some(model) = models.get_mut(child) {
+                if model.ref_count != ref_count {
+                    self.changed.entry(*child).or_insert(model.ref_count);
+                    model.ref_count = ref_count;
+                }
+            }
+        });
+    }
+}
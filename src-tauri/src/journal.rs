@@ -0,0 +1,133 @@
+// 修改日志：每次修改模型的命令都会先追加一条记录，保存后清空，正常退出时删除
+// 启动时若日志中仍有记录，说明上次未正常退出，可以将其重放以恢复未保存的修改
+use crate::schema::FileModel;
+use crate::{MergeStrategy, ModelId};
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(tag = "command")]
+pub enum JournalEntry {
+    UpdateNodeName { id: ModelId, new_name: String },
+    AddNode { parent_id: ModelId, new_id: ModelId, new_name: String },
+    InsertNodeAt { parent_id: ModelId, index: usize, new_id: ModelId, new_name: String },
+    DeleteNode { parent_id: ModelId, id: ModelId },
+    ToggleHasChildren { id: ModelId },
+    UpdateAlgorithm { id: ModelId, algorithm: String },
+    AddRoot { id: ModelId },
+    RemoveRoot { id: ModelId },
+    SetUniqueNames { unique_names: bool },
+    LinkExistingNode { parent_id: ModelId, id: ModelId },
+    // 副本的ID从new_id开始依次分配，重放时得到相同的ID
+    DeepCopySubtree { id: ModelId, new_parent_id: ModelId, new_id: ModelId },
+    MoveNode { old_parent_id: ModelId, new_parent_id: ModelId, id: ModelId },
+    MoveChild { parent_id: ModelId, id: ModelId, new_index: usize },
+    BatchRename { pattern: String, replacement: String },
+    MergeNodes { source_id: ModelId, target_id: ModelId, strategy: MergeStrategy },
+    // 记录解析后的模型而非文件路径，重放时不受文件之后修改的影响
+    ImportModels { models: Vec<FileModel> },
+    Undo,
+    Redo,
+}
+
+pub fn journal_path(models_file_path: &str) -> String {
+    format!("{}.journal", models_file_path)
+}
+
+pub fn append(journal_path: &str, entry: &JournalEntry) -> Result<()> {
+    let mut line = serde_json::to_string(entry).context("序列化日志记录错误")?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_path)
+        .context(format!("打开日志文件{:?}错误", journal_path))?;
+    file.write_all(line.as_bytes()).context(format!("写入日志文件{:?}错误", journal_path))?;
+    file.sync_data().context(format!("写入日志文件{:?}错误", journal_path))?;
+    Ok(())
+}
+
+pub fn read_entries(journal_path: &str) -> Result<Vec<JournalEntry>> {
+    if !Path::new(journal_path).exists() {
+        return Ok(vec![]);
+    }
+    let content = fs::read_to_string(journal_path)
+        .context(format!("读取日志文件{:?}错误", journal_path))?;
+    let lines = content.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<&str>>();
+    let mut entries = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        match serde_json::from_str::<JournalEntry>(line) {
+            Ok(entry) => entries.push(entry),
+            // 最后一条记录可能在写入过程中崩溃而不完整，丢弃即可
+            Err(e) if i == lines.len() - 1 => {
+                eprintln!("日志文件{:?}最后一条记录不完整，已忽略：{}", journal_path, e);
+            }
+            Err(e) => {
+                return Err(e).context(format!("解析日志文件{:?}第{}行错误", journal_path, i + 1));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+pub fn clear(journal_path: &str) -> Result<()> {
+    if Path::new(journal_path).exists() {
+        fs::remove_file(journal_path).context(format!("删除日志文件{:?}错误", journal_path))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每个测试使用单独的日志文件，测试并行运行时互不影响
+    fn temp_journal(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("journal_test_{}_{}.journal", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn missing_journal_has_no_entries() {
+        let path = temp_journal("missing");
+        assert!(read_entries(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn append_then_read_entries() {
+        let path = temp_journal("append");
+        append(&path, &JournalEntry::AddRoot { id: 1 }).unwrap();
+        append(&path, &JournalEntry::Undo).unwrap();
+        let entries = read_entries(&path).unwrap();
+        assert!(matches!(entries.as_slice(), [JournalEntry::AddRoot { id: 1 }, JournalEntry::Undo]));
+        clear(&path).unwrap();
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn truncated_last_entry_is_skipped() {
+        // append写入过程中崩溃，最后一行只写了一部分，也没有换行
+        let path = temp_journal("truncated");
+        append(&path, &JournalEntry::UpdateNodeName { id: 2, new_name: "B2".to_string() }).unwrap();
+        append(&path, &JournalEntry::AddRoot { id: 3 }).unwrap();
+        let line = serde_json::to_string(&JournalEntry::DeleteNode { parent_id: 1, id: 2 }).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&line.as_bytes()[..line.len() / 2]).unwrap();
+        drop(file);
+        let entries = read_entries(&path).unwrap();
+        assert!(matches!(entries.as_slice(), [JournalEntry::UpdateNodeName { id: 2, .. }, JournalEntry::AddRoot { id: 3 }]));
+        clear(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupted_entry_before_last_is_an_error() {
+        let path = temp_journal("corrupted");
+        fs::write(&path, "{\"command\":\"AddRo\n{\"command\":\"Undo\"}\n").unwrap();
+        let error = read_entries(&path).unwrap_err();
+        assert!(format!("{:#}", error).contains("第1行"));
+        clear(&path).unwrap();
+    }
+}
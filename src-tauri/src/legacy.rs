@@ -0,0 +1,74 @@
+// 旧版的模型目录：每个有子节点的模型单独保存为一个文件，文件名为模型名，例如"健康指数.json"
+// 叶节点模型不单独保存，读取时由children自动补全
+use crate::error::ModelError;
+use crate::lint::{Diagnostic, Severity};
+use crate::schema::FileModel;
+use crate::{build_models, write_models};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+// 读取目录中的所有模型文件，按文件名排序，同时返回文件之间的冲突
+pub fn read_legacy_dir(dir: &str) -> Result<(Vec<FileModel>, Vec<Diagnostic>)> {
+    let mut paths = fs::read_dir(dir)
+        .context(format!("未找到模型路径：{}", dir))?
+        .map(|entry| entry.map(|entry| entry.path()).context("路径入口错误"))
+        .collect::<Result<Vec<PathBuf>>>()?;
+    paths.retain(|path| path.is_file() && path.extension().map_or(false, |extension| extension == "json"));
+    paths.sort();
+    let mut file_models = Vec::new();
+    let mut diagnostics = Vec::new();
+    // 模型名 -> 定义该模型的文件
+    let mut sources: BTreeMap<String, Vec<(String, FileModel)>> = BTreeMap::new();
+    for path in paths {
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let content = fs::read_to_string(&path).context(format!("读取模型文件{:?}错误", file_name))?;
+        let model = serde_json::from_str::<FileModel>(&content).context(format!("解析模型文件{:?}错误", file_name))?;
+        if path.file_stem().map_or(true, |stem| stem.to_string_lossy() != model.name) {
+            diagnostics.push(Diagnostic::new(Severity::Warning, "legacy-file-name-mismatch", vec![model.name.clone()],
+                format!("模型文件{:?}中的模型名为{}，与文件名不一致", file_name, model.name)));
+        }
+        // 无children的模型不应单独存放
+        if model.children.is_none() {
+            diagnostics.push(Diagnostic::new(Severity::Warning, "legacy-missing-children", vec![model.name.clone()],
+                format!("模型文件{:?}中未找到children字段", file_name)));
+        }
+        sources.entry(model.name.clone()).or_default().push((file_name, model.clone()));
+        file_models.push(model);
+    }
+    sources.into_iter().filter(|(_, files)| files.len() > 1).for_each(|(name, files)| {
+        let file_names = files.iter().map(|(file_name, _)| format!("{:?}", file_name)).collect::<Vec<String>>().join("、");
+        let (_, first) = &files[0];
+        let identical = files.iter().all(|(_, model)| model.algorithm == first.algorithm && model.children == first.children);
+        if identical {
+            diagnostics.push(Diagnostic::new(Severity::Warning, "duplicate-model", vec![name.clone()],
+                format!("模型{}在文件{}中重复定义，内容相同", name, file_names)));
+        } else {
+            diagnostics.push(Diagnostic::new(Severity::Error, "legacy-conflict", vec![name.clone()],
+                format!("模型{}在文件{}中的定义互相冲突，只保留了{:?}中的定义", name, file_names, files[files.len() - 1].0)));
+        }
+    });
+    Ok((file_models, diagnostics))
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct MigrationReport {
+    // 迁移后模型文件中的模型数量，不包括叶节点
+    pub model_count: usize,
+    // 文件之间有冲突时不写入模型文件
+    pub written: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+// 将旧版的模型目录转换为单个模型文件，文件之间的定义互相冲突时需先手动解决，不写入文件
+pub fn migrate(dir: &str, file_path: &str) -> Result<MigrationReport, ModelError> {
+    let (file_models, diagnostics) = read_legacy_dir(dir).map_err(ModelError::io)?;
+    let written = !diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error);
+    let loaded = build_models(file_models)?;
+    if written {
+        write_models(file_path, &loaded.models, loaded.next_id, loaded.unique_names).map_err(ModelError::io)?;
+    }
+    let model_count = loaded.models.values().filter(|model| model.children.is_some()).count();
+    Ok(MigrationReport { model_count, written, diagnostics })
+}
@@ -1 +1,249 @@
+// 模型图库：模型的读取、检查、修改与保存，不依赖界面，供Tauri命令和命令行模式共用
+// 模型以固定的ID互相引用，ModelGraph维护模型、反向索引和撤销记录，所有修改都经过它
+use anyhow::{Context, Result};
+use error::ModelError;
+use algorithm::AlgorithmError;
+use index::ParentIndex;
+use lint::Diagnostic;
+use schema::{FileModel, FileModelV2, ModelFile};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
 
+pub mod algorithm;
+pub mod config;
+pub mod diagram;
+pub mod error;
+pub mod evaluate;
+pub mod events;
+pub mod flat;
+pub mod graph;
+pub mod history;
+pub mod index;
+pub mod journal;
+pub mod legacy;
+pub mod lint;
+mod model_graph;
+pub mod schema;
+pub mod search;
+
+pub use model_graph::{
+    BatchRenameReport, ImportReport, MergeReport, MergeStrategy, ModelDiff, ModelGraph, NameCollision, PlannedRename, UpdateNameResponse,
+};
+
+// 模型的固定ID，重命名不改变ID，children以ID引用子节点
+pub type ModelId = u64;
+
+// the actual representation in the backend
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Model{
+    pub id: ModelId,
+    // 只用于显示，不要求名称唯一时可以与其他模型重名
+    pub name: String,
+    pub algorithm: Option<String>,
+    pub children: Option<Vec<ModelId>>,
+    pub ref_count: u64,
+    // 被设为入口的模型即使有父节点也会出现在根节点列表中，从所有父节点中删除后仍然保留
+    pub pinned: bool,
+}
+
+// API
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Node {
+    pub id: ModelId,
+    pub name: String,
+    pub ref_count: u64,
+    pub has_children: bool,
+    pub pinned: bool,
+}
+
+impl From<&Model> for Node {
+    fn from(model: &Model) -> Self {
+        Node {
+            id: model.id,
+            name: model.name.clone(),
+            ref_count: model.ref_count,
+            has_children: model.children.is_some(),
+            pinned: model.pinned,
+        }
+    }
+}
+
+// 从顶层模型到某个模型的一条路径
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct UsagePath {
+    pub ids: Vec<ModelId>,
+    pub names: Vec<String>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct UsagePaths {
+    pub paths: Vec<UsagePath>,
+    // 被多层共享的模型的路径数随层数成倍增长，超过MAX_USAGE_PATHS时只返回一部分
+    pub truncated: bool,
+}
+
+// 从模型文件读入的模型及文件中的设置
+pub struct LoadedModels {
+    pub models: HashMap<ModelId, Model>,
+    pub parents: ParentIndex,
+    pub next_id: ModelId,
+    pub unique_names: bool,
+    // 只能在读取文件时发现的问题
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+// 唯一没有父节点的模型即为根节点
+fn detect_root_id(models: &HashMap<ModelId, Model>) -> Result<ModelId, ModelError> {
+    let mut roots = models.values().filter(|model| model.ref_count == 0).collect::<Vec<&Model>>();
+    roots.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+    match roots.as_slice() {
+        [root] => Ok(root.id),
+        [] => Err(ModelError::invalid_root(vec![], "未找到没有父节点的模型，无法确定根节点".to_string())),
+        _ => {
+            let names = roots.iter().map(|model| model.name.as_str()).collect::<Vec<&str>>();
+            let message = format!("有多个没有父节点的模型，请指定根节点：{}", names.join("、"));
+            Err(ModelError::invalid_root(roots.iter().map(|model| model.id).collect(), message))
+        }
+    }
+}
+
+// file_path可以是模型文件，也可以是旧版的模型目录
+// 读写文件的错误为ModelError::Io，模型数据本身的问题（格式、环、不存在的子节点）为对应的类型
+pub fn load_models(file_path: &str) -> Result<LoadedModels, ModelError>{
+    let (mut loaded, diagnostics) = if Path::new(&file_path).is_dir() {
+        let (file_models, diagnostics) = legacy::read_legacy_dir(file_path).map_err(ModelError::io)?;
+        (build_models(file_models)?, diagnostics)
+    } else {
+        let content = fs::read_to_string(file_path)
+            .context(format!("读取模型文件{:?}错误", file_path)).map_err(ModelError::io)?;
+        match schema::parse(&content)? {
+            schema::AnyModelFile::V1(file_models) => {
+                let diagnostics = lint::lint_file_models(&file_models);
+                (build_models(file_models)?, diagnostics)
+            }
+            schema::AnyModelFile::V2(model_file) => {
+                let diagnostics = lint::lint_model_file(&model_file);
+                (build_models_v2(model_file)?, diagnostics)
+            }
+        }
+    };
+    loaded.diagnostics = diagnostics;
+    Ok(loaded)
+}
+
+// 由版本1的模型构建后端的模型，重名的模型保留最后一个
+// 按名称排序分配ID，同一个文件每次读入得到的ID相同
+pub fn build_models(file_models: Vec<FileModel>) -> Result<LoadedModels, ModelError> {
+    let mut models: HashMap<String, FileModel> = file_models.into_iter().map(|model|{
+        (model.name.clone(), model)
+    }).collect();
+    // 遍历所有模型及其children，将所有名字放入集合中
+    let mut names = HashSet::new();
+    models.iter().for_each(|(_name, model)| {
+        names.insert(model.name.clone());
+        if let Some(children) = &model.children {
+            children.iter().for_each(|child| {
+                names.insert(child.clone());
+            });
+        }
+    });
+    // 在原有模型集合的基础上加入叶节点模型
+    names.iter().for_each(|name| {
+        if !models.contains_key(name) {
+            models.insert(name.clone(), FileModel{name: name.clone(), algorithm: None, children: None, pinned: false});
+        }
+    });
+    let mut names = names.into_iter().collect::<Vec<String>>();
+    names.sort();
+    let ids = names.iter().enumerate().map(|(index, name)| (name.clone(), index as ModelId + 1)).collect::<HashMap<String, ModelId>>();
+    let mut models = models.into_iter().map::<(ModelId, Model),_>(|(name, model)| {
+        let id = ids[&name];
+        let children = model.children.map(|children| children.iter().map(|child| ids[child]).collect());
+        (id, Model{id, name: model.name, algorithm: model.algorithm, children, ref_count: 0, pinned: model.pinned})
+    }).collect();
+    let parents = check_models(&mut models)?;
+    // 版本1中模型以名称互相引用，名称必然唯一
+    Ok(LoadedModels { models, parents, next_id: names.len() as ModelId + 1, unique_names: true, diagnostics: vec![] })
+}
+
+fn build_models_v2(model_file: ModelFile) -> Result<LoadedModels, ModelError> {
+    let mut models = model_file.models.into_iter().map::<(ModelId, Model),_>(|model| {
+        (model.id, Model{id: model.id, name: model.name, algorithm: model.algorithm, children: model.children, ref_count: 0, pinned: model.pinned})
+    }).collect::<HashMap<ModelId, Model>>();
+    for model in models.values() {
+        if let Some(child) = model.children.iter().flatten().find(|child| !models.contains_key(child)) {
+            return Err(ModelError::NotFound { id: *child, message: format!("模型{}的子节点ID {}不存在", model.name, child) });
+        }
+    }
+    let parents = check_models(&mut models)?;
+    let next_id = models.keys().max().map_or(1, |id| id + 1).max(model_file.next_id);
+    Ok(LoadedModels { models, parents, next_id, unique_names: model_file.unique_names, diagnostics: vec![] })
+}
+
+// 建立反向索引并计算引用计数，检查模型图中的环
+fn check_models(models: &mut HashMap<ModelId, Model>) -> Result<ParentIndex, ModelError> {
+    let parents = ParentIndex::build(models);
+    // 有环的模型会使前端无限展开
+    let cycles = graph::validate_graph(models);
+    // 有多个环时path为第一个环，message列出所有的环
+    if let Some(path) = cycles.first() {
+        let messages = cycles.iter().map(|path| ModelError::cycle_detected(path.clone(), models).to_string()).collect::<Vec<String>>();
+        return Err(ModelError::CycleDetected { path: path.clone(), message: messages.join("；") });
+    }
+    Ok(parents)
+}
+
+// 叶节点也单独保存，叶节点的名称不再能由父节点的children得到
+pub fn models_to_file(models: &HashMap<ModelId, Model>, next_id: ModelId, unique_names: bool) -> ModelFile {
+    let mut file_models = models.values().map(|model| {
+        FileModelV2{id: model.id, name: model.name.clone(), algorithm: model.algorithm.clone(), children: model.children.clone(), pinned: model.pinned}
+    }).collect::<Vec<FileModelV2>>();
+    file_models.sort_by_key(|model| model.id);
+    ModelFile { models: file_models, next_id, unique_names, version: schema::CURRENT_VERSION }
+}
+
+pub fn write_models(file_path: &str, models: &HashMap<ModelId, Model>, next_id: ModelId, unique_names: bool) -> Result<()> {
+    let content = schema::to_bytes(&models_to_file(models, next_id, unique_names))?;
+    // 先写入临时文件再重命名，写入过程中崩溃不会损坏原模型文件
+    let temp_file_path = format!("{}.tmp", file_path);
+    let mut file = fs::File::create(&temp_file_path)
+        .context(format!("创建临时文件{:?}错误", temp_file_path))?;
+    file.write_all(&content).context(format!("写入临时文件{:?}错误", temp_file_path))?;
+    file.sync_all().context(format!("写入临时文件{:?}错误", temp_file_path))?;
+    drop(file);
+    fs::rename(&temp_file_path, file_path)
+        .context(format!("保存模型文件{:?}错误", file_path))?;
+    Ok(())
+}
+
+// 用于消息中显示模型，模型不存在时显示ID
+pub fn model_label(id: ModelId, models: &HashMap<ModelId, Model>) -> String {
+    models.get(&id).map_or_else(|| format!("ID {}", id), |model| model.name.clone())
+}
+
+// 名为name的所有模型，按ID排序
+pub fn find_by_name(name: &str, models: &HashMap<ModelId, Model>) -> Vec<ModelId> {
+    let mut ids = models.values().filter(|model| model.name == name).map(|model| model.id).collect::<Vec<ModelId>>();
+    ids.sort_unstable();
+    ids
+}
+
+// 获取有子节点的模型的children，修改后通过ParentIndex::set_children写回
+pub(crate) fn composite_children(id: ModelId, models: &HashMap<ModelId, Model>) -> Result<Vec<ModelId>, ModelError>{
+    let model = models.get(&id).ok_or_else(|| ModelError::not_found(id))?;
+    model.children.clone().ok_or_else(|| ModelError::not_a_composite(model))
+}
+
+// 子节点的名称，算法以名称引用子节点；不同的子节点同名时算法无法区分
+pub fn children_labels(model: &Model, models: &HashMap<ModelId, Model>) -> Result<Vec<String>, ModelError>{
+    let mut seen: HashMap<&str, ModelId> = HashMap::new();
+    model.children.iter().flatten().map(|child| {
+        let name = models.get(child).map(|child| child.name.as_str()).ok_or_else(|| ModelError::not_found(*child))?;
+        if seen.insert(name, *child).map_or(false, |other| other != *child){
+            return Err(ModelError::invalid_algorithm(model, AlgorithmError::new(format!("有多个子节点名为{}，算法无法区分", name))));
+        }
+        Ok(name.to_string())
+    }).collect()
+}
@@ -0,0 +1,397 @@
+// 模型检查：找出模型文件和模型图中的问题，不修改模型
+use crate::algorithm;
+use crate::graph::{topological_order, validate_graph};
+use crate::schema::{FileModel, ModelFile};
+use crate::{children_labels, model_label, Model, ModelId};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // 问题类别，例如"duplicate-model"
+    pub code: String,
+    // 涉及的模型的名称
+    pub models: Vec<String>,
+    // 涉及的模型的ID，读取版本1的文件时发现的问题还没有ID
+    pub ids: Vec<ModelId>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &str, models: Vec<String>, message: String) -> Self {
+        Diagnostic { severity, code: code.to_string(), models, ids: vec![], message }
+    }
+
+    fn with_ids(severity: Severity, code: &str, ids: Vec<ModelId>, models: &HashMap<ModelId, Model>, message: String) -> Self {
+        let names = ids.iter().map(|id| model_label(*id, models)).collect();
+        Diagnostic { severity, code: code.to_string(), models: names, ids, message }
+    }
+}
+
+// 只能在读取文件时发现的问题：同名的模型在读入时后者会覆盖前者
+pub fn lint_file_models(file_models: &[FileModel]) -> Vec<Diagnostic> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    file_models.iter().for_each(|model| *counts.entry(model.name.as_str()).or_insert(0) += 1);
+    counts.into_iter().filter(|(_, count)| *count > 1).map(|(name, count)| {
+        Diagnostic::new(Severity::Error, "duplicate-model", vec![name.to_string()],
+            format!("模型{}在文件中定义了{}次，只保留了最后一次定义", name, count))
+    }).collect()
+}
+
+// 版本2的文件中ID重复的模型，读入时后者会覆盖前者
+pub fn lint_model_file(model_file: &ModelFile) -> Vec<Diagnostic> {
+    let mut definitions: BTreeMap<ModelId, Vec<String>> = BTreeMap::new();
+    model_file.models.iter().for_each(|model| definitions.entry(model.id).or_default().push(model.name.clone()));
+    definitions.into_iter().filter(|(_, names)| names.len() > 1).map(|(id, names)| {
+        let message = format!("ID {}在文件中定义了{}次（{}），只保留了最后一次定义", id, names.len(), names.join("、"));
+        let mut diagnostic = Diagnostic::new(Severity::Error, "duplicate-id", names, message);
+        diagnostic.ids = vec![id];
+        diagnostic
+    }).collect()
+}
+
+pub fn lint_models(models: &HashMap<ModelId, Model>, root_id: ModelId, unique_names: bool) -> Vec<Diagnostic> {
+    let mut sorted = models.values().collect::<Vec<&Model>>();
+    sorted.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+    let mut diagnostics = Vec::new();
+    for model in &sorted {
+        let (id, name) = (model.id, &model.name);
+        if model.children.is_some() != model.algorithm.is_some() {
+            let (children, algorithm) = if model.children.is_some() { ("有", "无") } else { ("无", "有") };
+            diagnostics.push(Diagnostic::with_ids(Severity::Error, "children-algorithm-mismatch", vec![id], models,
+                format!("模型{}状态冲突：{}子节点但{}算法声明", name, children, algorithm)));
+        }
+        if let Some(children) = &model.children {
+            if children.is_empty() {
+                diagnostics.push(Diagnostic::with_ids(Severity::Warning, "empty-children", vec![id], models,
+                    format!("模型{}的子节点列表为空", name)));
+            }
+            let mut seen = HashSet::new();
+            let mut duplicated = Vec::new();
+            children.iter().for_each(|child| {
+                if !seen.insert(*child) && !duplicated.contains(child) {
+                    duplicated.push(*child);
+                }
+            });
+            duplicated.into_iter().for_each(|child| {
+                diagnostics.push(Diagnostic::with_ids(Severity::Warning, "duplicate-child", vec![id, child], models,
+                    format!("模型{}的子节点中{}出现了多次", name, model_label(child, models))));
+            });
+            // 不同的子节点同名时算法无法区分
+            match children_labels(model, models) {
+                Ok(labels) => {
+                    if let Some(source) = &model.algorithm {
+                        if let Err(e) = algorithm::validate(source, &labels) {
+                            diagnostics.push(Diagnostic::with_ids(Severity::Error, "invalid-algorithm", vec![id], models,
+                                format!("模型{}的算法有误：{}", name, e)));
+                        }
+                    }
+                }
+                Err(e) => {
+                    diagnostics.push(Diagnostic::with_ids(Severity::Warning, "ambiguous-child-name", vec![id], models, e.message().to_string()));
+                }
+            }
+        }
+        if name.as_str() != name.trim() {
+            diagnostics.push(Diagnostic::with_ids(Severity::Warning, "untrimmed-name", vec![id], models,
+                format!("模型名称{:?}首尾有空白字符", name)));
+        }
+    }
+    if unique_names {
+        diagnostics.extend(lint_duplicate_names(&sorted, models));
+    }
+    diagnostics.extend(lint_width_variants(&sorted, models));
+    diagnostics.extend(validate_graph(models).into_iter().map(|path| {
+        let names = path.iter().map(|id| model_label(*id, models)).collect::<Vec<String>>();
+        let message = format!("模型之间存在循环引用：{}", names.join(" → "));
+        Diagnostic::with_ids(Severity::Error, "cycle", path, models, message)
+    }));
+    diagnostics.extend(lint_unreachable(models, root_id));
+    diagnostics
+}
+
+// 要求名称唯一时，同名的不同模型
+fn lint_duplicate_names(sorted: &[&Model], models: &HashMap<ModelId, Model>) -> Vec<Diagnostic> {
+    let mut groups: BTreeMap<&str, Vec<ModelId>> = BTreeMap::new();
+    sorted.iter().for_each(|model| groups.entry(model.name.as_str()).or_default().push(model.id));
+    groups.into_iter().filter(|(_, ids)| ids.len() > 1).map(|(name, ids)| {
+        let message = format!("有{}个模型名为{}，但模型文件要求名称唯一", ids.len(), name);
+        Diagnostic::with_ids(Severity::Error, "duplicate-name", ids, models, message)
+    }).collect()
+}
+
+// 全角字符转为对应的半角字符并去掉首尾空白，用于发现只有全角/半角区别的名称
+fn normalize_width(name: &str) -> String {
+    name.trim().chars().map(|c| match c {
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }).collect()
+}
+
+fn lint_width_variants(sorted: &[&Model], models: &HashMap<ModelId, Model>) -> Vec<Diagnostic> {
+    let mut groups: BTreeMap<String, Vec<&Model>> = BTreeMap::new();
+    sorted.iter().for_each(|model| groups.entry(normalize_width(&model.name)).or_default().push(model));
+    groups.into_values().filter_map(|group| {
+        let mut names = group.iter().map(|model| model.name.as_str()).collect::<Vec<&str>>();
+        names.dedup();
+        if names.len() < 2 {
+            return None;
+        }
+        let message = format!("以下模型名称只有全角/半角或空白的区别，可能是同一个模型：{}", names.join("、"));
+        Some(Diagnostic::with_ids(Severity::Warning, "width-variant", group.iter().map(|model| model.id).collect(), models, message))
+    }).collect()
+}
+
+// 无法从根节点或固定的入口到达的模型，按没有父节点的顶层模型分组报告
+fn lint_unreachable(models: &HashMap<ModelId, Model>, root_id: ModelId) -> Vec<Diagnostic> {
+    let root_name = model_label(root_id, models);
+    let mut reachable = match topological_order(root_id, models) {
+        Ok(order) => order.into_iter().collect::<HashSet<ModelId>>(),
+        Err(_) => {
+            if models.contains_key(&root_id) {
+                // 有环的情况已单独报告
+                return vec![];
+            }
+            let mut diagnostic = Diagnostic::new(Severity::Error, "missing-root", vec![root_name.clone()],
+                format!("未找到根节点{}", root_name));
+            diagnostic.ids = vec![root_id];
+            return vec![diagnostic];
+        }
+    };
+    // 固定的入口有意不被引用，视为额外的根节点
+    models.values().filter(|model| model.pinned).for_each(|model| {
+        reachable.extend(topological_order(model.id, models).unwrap_or_default());
+    });
+    let mut tops = models.values().filter(|model| model.ref_count == 0 && !reachable.contains(&model.id))
+        .collect::<Vec<&Model>>();
+    tops.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+    tops.into_iter().map(|top| {
+        let mut unreachable = topological_order(top.id, models).unwrap_or_default()
+            .into_iter().filter(|id| !reachable.contains(id)).collect::<Vec<ModelId>>();
+        // 顶层模型排在最前
+        unreachable.retain(|id| *id != top.id);
+        let message = if unreachable.is_empty() {
+            format!("模型{}无法从根节点{}到达", top.name, root_name)
+        } else {
+            format!("模型{}（连同其下{}个模型）无法从根节点{}到达", top.name, unreachable.len(), root_name)
+        };
+        unreachable.insert(0, top.id);
+        Diagnostic::with_ids(Severity::Warning, "unreachable", unreachable, models, message)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::ParentIndex;
+    use crate::schema::FileModelV2;
+    use crate::{build_models, find_by_name, ModelGraph};
+
+    fn composite(name: &str, children: &[&str], algorithm: &str) -> FileModel {
+        FileModel {
+            name: name.to_string(),
+            children: Some(children.iter().map(|child| child.to_string()).collect()),
+            algorithm: Some(algorithm.to_string()),
+            pinned: false,
+        }
+    }
+
+    // A = B + C，B = D，C = D * E
+    fn sample() -> Vec<FileModel> {
+        vec![composite("A", &["B", "C"], "B + C"), composite("B", &["D"], "D"), composite("C", &["D", "E"], "D * E")]
+    }
+
+    fn id(name: &str, models: &HashMap<ModelId, Model>) -> ModelId {
+        find_by_name(name, models)[0]
+    }
+
+    // 修改sample()中的模型后检查，根节点为A
+    fn lint(edit: impl FnOnce(&mut HashMap<ModelId, Model>)) -> Vec<Diagnostic> {
+        let mut models = build_models(sample()).unwrap().models;
+        edit(&mut models);
+        ParentIndex::build(&mut models);
+        let root_id = id("A", &models);
+        lint_models(&models, root_id, true)
+    }
+
+    // 只有一个诊断时返回它
+    fn single(diagnostics: Vec<Diagnostic>) -> Diagnostic {
+        match diagnostics.as_slice() {
+            [diagnostic] => diagnostic.clone(),
+            _ => panic!("应只有一个诊断：{:?}", diagnostics),
+        }
+    }
+
+    // 改名会使引用它的算法出错，只取指定类别的诊断
+    fn find(diagnostics: &[Diagnostic], code: &str) -> Diagnostic {
+        let found = diagnostics.iter().filter(|diagnostic| diagnostic.code == code).cloned().collect::<Vec<Diagnostic>>();
+        single(found)
+    }
+
+    fn set_name(name: &str, new_name: &str, models: &mut HashMap<ModelId, Model>) {
+        let id = id(name, models);
+        models.get_mut(&id).unwrap().name = new_name.to_string();
+    }
+
+    #[test]
+    fn sample_has_no_diagnostics() {
+        assert_eq!(lint(|_| {}), vec![]);
+    }
+
+    #[test]
+    fn children_algorithm_mismatch() {
+        let diagnostic = single(lint(|models| {
+            let b = id("B", models);
+            models.get_mut(&b).unwrap().algorithm = None;
+        }));
+        assert_eq!((diagnostic.severity, diagnostic.code.as_str()), (Severity::Error, "children-algorithm-mismatch"));
+        assert_eq!(diagnostic.models, ["B"]);
+    }
+
+    #[test]
+    fn empty_children() {
+        // D不再被引用，但仍可经C到达
+        let diagnostic = single(lint(|models| {
+            let b = id("B", models);
+            let model = models.get_mut(&b).unwrap();
+            model.children = Some(vec![]);
+            model.algorithm = Some("待定".to_string());
+        }));
+        assert_eq!((diagnostic.severity, diagnostic.code.as_str()), (Severity::Warning, "empty-children"));
+        assert_eq!(diagnostic.models, ["B"]);
+    }
+
+    #[test]
+    fn duplicate_child() {
+        let diagnostic = single(lint(|models| {
+            let (c, d) = (id("C", models), id("D", models));
+            models.get_mut(&c).unwrap().children.as_mut().unwrap().push(d);
+        }));
+        assert_eq!((diagnostic.severity, diagnostic.code.as_str()), (Severity::Warning, "duplicate-child"));
+        assert_eq!(diagnostic.models, ["C", "D"]);
+    }
+
+    #[test]
+    fn duplicate_child_after_rename_merge() {
+        // B同时引用D和E，D改名为E时合并到E，B的子节点中E出现两次
+        let mut graph = ModelGraph::from_file_models(vec![composite("A", &["B"], "B"), composite("B", &["D", "E"], "D + E")]).unwrap();
+        let d = id("D", graph.models());
+        graph.rename(d, "E").unwrap();
+        let diagnostic = single(graph.diagnostics());
+        assert_eq!(diagnostic.code, "duplicate-child");
+        assert_eq!(diagnostic.models, ["B", "E"]);
+    }
+
+    #[test]
+    fn invalid_algorithm() {
+        let diagnostic = single(lint(|models| {
+            let c = id("C", models);
+            models.get_mut(&c).unwrap().algorithm = Some("D * F".to_string());
+        }));
+        assert_eq!((diagnostic.severity, diagnostic.code.as_str()), (Severity::Error, "invalid-algorithm"));
+        assert!(diagnostic.message.contains("F不是子节点"));
+    }
+
+    #[test]
+    fn ambiguous_child_name() {
+        // 不要求名称唯一时，C的两个子节点同名
+        let mut models = build_models(sample()).unwrap().models;
+        set_name("E", "D", &mut models);
+        ParentIndex::build(&mut models);
+        let diagnostics = lint_models(&models, id("A", &models), false);
+        let diagnostic = single(diagnostics);
+        assert_eq!((diagnostic.severity, diagnostic.code.as_str()), (Severity::Warning, "ambiguous-child-name"));
+        assert_eq!(diagnostic.models, ["C"]);
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let diagnostic = find(&lint(|models| set_name("E", "D", models)), "duplicate-name");
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.models, ["D", "D"]);
+    }
+
+    #[test]
+    fn untrimmed_name() {
+        let diagnostic = find(&lint(|models| set_name("D", "D ", models)), "untrimmed-name");
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(diagnostic.models, ["D "]);
+    }
+
+    #[test]
+    fn width_variant() {
+        // 全角的Ｄ与半角的D
+        let diagnostic = find(&lint(|models| set_name("E", "Ｄ", models)), "width-variant");
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(diagnostic.models, ["D", "Ｄ"]);
+    }
+
+    #[test]
+    fn cycle() {
+        let diagnostics = lint(|models| {
+            let (a, d) = (id("A", models), id("D", models));
+            let model = models.get_mut(&d).unwrap();
+            model.children = Some(vec![a]);
+            model.algorithm = Some("A".to_string());
+        });
+        let diagnostic = single(diagnostics);
+        assert_eq!((diagnostic.severity, diagnostic.code.as_str()), (Severity::Error, "cycle"));
+        assert_eq!(diagnostic.models.first(), diagnostic.models.last());
+    }
+
+    #[test]
+    fn unreachable() {
+        // F → [G]不被任何模型引用
+        let diagnostic = single(lint(|models| {
+            models.insert(100, Model { id: 100, name: "F".to_string(), algorithm: Some("G".to_string()), children: Some(vec![101]), ref_count: 0, pinned: false });
+            models.insert(101, Model { id: 101, name: "G".to_string(), algorithm: None, children: None, ref_count: 0, pinned: false });
+        }));
+        assert_eq!((diagnostic.severity, diagnostic.code.as_str()), (Severity::Warning, "unreachable"));
+        assert_eq!(diagnostic.ids, [100, 101]);
+        assert!(diagnostic.message.contains("连同其下1个模型"));
+    }
+
+    #[test]
+    fn pinned_entry_points_are_reachable() {
+        // 固定的F及其子节点G不报告，H → [G]仍报告，但不包括已可从F到达的G
+        let diagnostic = single(lint(|models| {
+            models.insert(100, Model { id: 100, name: "F".to_string(), algorithm: Some("G".to_string()), children: Some(vec![101]), ref_count: 0, pinned: true });
+            models.insert(101, Model { id: 101, name: "G".to_string(), algorithm: None, children: None, ref_count: 0, pinned: false });
+            models.insert(102, Model { id: 102, name: "H".to_string(), algorithm: Some("G".to_string()), children: Some(vec![101]), ref_count: 0, pinned: false });
+        }));
+        assert_eq!(diagnostic.code, "unreachable");
+        assert_eq!(diagnostic.ids, [102]);
+    }
+
+    #[test]
+    fn missing_root() {
+        let models = build_models(sample()).unwrap().models;
+        let diagnostic = single(lint_models(&models, 100, true));
+        assert_eq!((diagnostic.severity, diagnostic.code.as_str()), (Severity::Error, "missing-root"));
+    }
+
+    #[test]
+    fn duplicate_model_in_version_1_file() {
+        let mut file_models = sample();
+        file_models.push(composite("B", &["E"], "E"));
+        let diagnostic = single(lint_file_models(&file_models));
+        assert_eq!((diagnostic.severity, diagnostic.code.as_str()), (Severity::Error, "duplicate-model"));
+        assert_eq!(diagnostic.models, ["B"]);
+    }
+
+    #[test]
+    fn duplicate_id_in_version_2_file() {
+        let model = |id: ModelId, name: &str| FileModelV2 { id, name: name.to_string(), algorithm: None, children: None, pinned: false };
+        let model_file = ModelFile { models: vec![model(1, "A"), model(2, "B"), model(2, "C")], next_id: 3, unique_names: true, version: 2 };
+        let diagnostic = single(lint_model_file(&model_file));
+        assert_eq!((diagnostic.severity, diagnostic.code.as_str()), (Severity::Error, "duplicate-id"));
+        assert_eq!((diagnostic.models.as_slice(), diagnostic.ids.as_slice()), (["B".to_string(), "C".to_string()].as_slice(), [2].as_slice()));
+    }
+}
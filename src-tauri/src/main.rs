@@ -1,39 +1,66 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-use anyhow::{Context, Result};
-use std::collections::{HashMap, HashSet};
-use std::fs::{self, ReadDir};
+use anyhow::Result;
+use app::diagram::DiagramFormat;
+use app::error::ModelError;
+use app::evaluate::Evaluation;
+use app::flat::{self, FlatFormat};
+use app::journal::{self, JournalEntry};
+use app::config;
+use app::legacy;
+use app::lint::Diagnostic;
+use app::search::{SearchMode, SearchResults};
+use app::{
+    events, graph, BatchRenameReport, ImportReport, MergeReport, MergeStrategy, ModelGraph, ModelId, Node,
+    UpdateNameResponse, UsagePaths,
+};
+use std::collections::HashMap;
+use std::path::Path;
 use std::process::exit;
 use std::sync::Mutex;
-// from files
-#[derive(serde::Serialize, serde::Deserialize, Clone)]
-struct FileModel {
-    name: String,
-    algorithm: Option<String>,
-    children: Option<Vec<String>>,
-}
+use tauri::Manager;
 
-// the actual representation in the backend
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
-struct Model{
-    name: String,
-    algorithm: Option<String>,
-    children: Option<Vec<String>>,
-    ref_count: u64,
-}
+// 与model-cli共用命令行模式的实现
+#[path = "bin/model-cli/cli.rs"]
+mod cli;
+use cli::print_diagnostic;
 
-// API
-#[derive(serde::Serialize, serde::Deserialize, Clone)]
-struct Node {
-    name: String,
-    ref_count: u64,
-    has_children: bool
-}
+const MAX_USAGE_PATHS: usize = 1000;
 
-#[derive(Clone)]
+// 搜索结果的数量上限，只返回排在前面的部分
+const MAX_SEARCH_RESULTS: usize = 200;
+
+// 模型的读取、修改与撤销见lib.rs中的ModelGraph，这里只保存与界面相关的状态
 struct TauriState {
-    models: HashMap<String, Model>,
-    root_name: String,
+    graph: ModelGraph,
+    models_file_path: String,
+    // 启动时在日志中发现的未保存修改，等待前端决定重放或丢弃
+    pending_journal: Vec<JournalEntry>,
+}
+
+impl TauriState {
+    // 读取模型，root_name为None时自动推断根节点
+    fn load(models_file_path: String, root_name: Option<String>) -> Result<TauriState, ModelError> {
+        let graph = ModelGraph::load(&models_file_path, root_name.as_deref())?;
+        // 日志中仍有记录说明上次未正常退出
+        let pending_journal = journal::read_entries(&journal::journal_path(&models_file_path)).map_err(ModelError::io)?;
+        Ok(TauriState { graph, models_file_path, pending_journal })
+    }
+
+    // 读取模型并检查，在控制台输出模型及发现的问题
+    fn open(models_file_path: String, root_name: Option<String>) -> Result<TauriState, ModelError> {
+        let state = TauriState::load(models_file_path, root_name)?;
+        state.graph.models().iter().for_each(|(id, model)| {
+            println!("模型{}（ID {}）：算法: {:?}，子节点: {:?}，引用计数: {}", model.name, id, model.algorithm, model.children, model.ref_count);
+        });
+        let root_id = state.graph.root_id();
+        println!("模型路径：{:?}，根节点：{}", state.models_file_path, state.graph.models()[&root_id].name);
+        state.graph.diagnostics().iter().for_each(print_diagnostic);
+        if !state.pending_journal.is_empty() {
+            println!("检测到上次未正常退出，日志中有{}条未保存的修改", state.pending_journal.len());
+        }
+        Ok(state)
+    }
 }
 
 // rust side keep track of instances ...
@@ -45,135 +72,89 @@ struct TauriState {
 // have a context with id -> function pair
 // all the children are queried
 fn main() {
+    // 命令行模式不打开窗口，输出只包含命令的结果，见cli.rs
+    // Windows的发布版本没有控制台，看不到输出，需使用单独的model-cli
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("--cli") {
+        exit(cli::run(args.collect()));
+    }
     println!("Current Directory: {:?}", std::env::current_dir().unwrap());
-    let models_file_path = "../models/model.json".to_string();
-    let root_name = "健康指数".to_string();
-    let models = match load_models(models_file_path) {
-        Ok(models) => models,
+    let (models_file_path, root_name) = match config::load() {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("Error: {}", e);
+            eprintln!("Error: {:#}", e);
             exit(1);
         }
     };
-    
-    models.iter().for_each(|(name, model)| {
-        println!("模型{}：算法: {:?}，子节点: {:?}，引用计数: {}", name, model.algorithm, model.children, model.ref_count);
-    });
-    let tauri_state = Mutex::new(TauriState { models, root_name });
-    tauri::Builder::default()
+    let tauri_state = match TauriState::open(models_file_path, root_name) {
+        Ok(state) => Mutex::new(state),
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            exit(1);
+        }
+    };
+    let app = tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
             update_node_name,
+            batch_rename,
             add_node,
+            insert_node_at,
+            move_child,
             delete_node,
-            query_root_name,
+            preview_delete_node,
+            link_existing_node,
+            deep_copy_subtree,
+            move_node,
+            merge_nodes,
+            query_root,
             query_node,
             query_children,
             query_algorithm,
             query_ref_count,
+            query_parents,
+            query_usage_paths,
+            search_models,
             toggle_has_children,
+            preview_toggle_has_children,
             update_algorithm,
+            save_models,
+            save_models_as,
+            query_pending_journal,
+            replay_journal,
+            discard_journal,
+            undo,
+            redo,
+            evaluate_algorithm,
+            evaluate,
+            validate_graph,
+            lint_models,
+            export_graph,
+            export_flat,
+            import_flat,
+            migrate_legacy_models,
+            open_model_file,
+            query_roots,
+            set_root,
+            add_root,
+            remove_root,
+            set_unique_names,
             log
         ])
         .manage(tauri_state)
-        .run(tauri::generate_context!())
+        .build(tauri::generate_context!())
         .expect("error while running tauri application");
-}
-
-fn load_models(file_path: String) -> Result<HashMap<String, Model>>{
-    let content = fs::read_to_string(&file_path)
-        .context(format!("读取模型文件{:?}错误", file_path))?;
-    let models = serde_json::from_str::<Vec<FileModel>>(&content).context("解析模型文件错误")?;
-    let mut models: HashMap<String, FileModel> = models.into_iter().map(|model|{
-        (model.name.clone(), model)
-    }).collect();
-    // 遍历所有模型及其children，将所有名字放入集合中
-    let mut names = HashSet::new();
-    models.iter().for_each(|(_name, model)| {
-        names.insert(model.name.clone());
-        if let Some(children) = &model.children {
-            children.iter().for_each(|child| {
-                names.insert(child.clone());
-            });
-        }
-    });
-    // 在原有模型集合的基础上加入叶节点模型
-    names.iter().for_each(|name| {
-        if !models.contains_key(name) {
-            models.insert(name.clone(), FileModel{name: name.clone(), algorithm: None, children: None});
-        }
-    });
-    let mut ref_counts = models.iter().map::<(String, u64), _>(|(name, _model)| {
-        (name.to_string(), 0)
-    }).collect::<HashMap<String, u64>>();
-    models.iter().for_each(|(_name, model)| {
-        if let Some(children) = &model.children {
-            children.iter().for_each(|child| {
-                let count = ref_counts.get(child).expect(format!("child {} does not exist in ref count", child).as_str());
-                ref_counts.insert(child.clone(), count + 1);
-            });
+    app.run(|app_handle, event| {
+        if let tauri::RunEvent::Exit = event {
+            // 正常退出时自动保存未保存的修改，保存成功后删除日志，下次启动时不再提示恢复；
+            // 保存失败或尚未决定是否恢复上次的修改时保留日志
+            let state = app_handle.state::<Mutex<TauriState>>();
+            let mut state = state.lock().unwrap();
+            if let Err(e) = save(&mut state) {
+                eprintln!("Error: 退出时自动保存失败，修改保留在日志中：{}", e);
+            }
         }
     });
-    let models = models.into_iter().map::<(String, Model),_>(|(name, model)| {
-        (name.clone(), Model{name: model.name, algorithm: model.algorithm, children: model.children, ref_count: ref_counts.get(&name).expect("model does not exist in ref count").clone()})
-    }).collect();
-    Ok(models)
-}
-
-
-// fn load_models(dir: String) -> Result<HashMap<String, Model>> {
-//     let mut file_models = fs::read_dir(dir.clone())
-//         .context(format!("未找到模型路径：{}", dir))?
-//         .map(|entry| entry.context("路径入口错误")) // Get Result<Result<DirEntry>>
-//         .collect::<Result<Vec<_>, _>>()? // short circuit error handling
-//         .iter()
-//         .map(|entry| entry.path()) // Get file paths
-//         .filter(|path| path.is_file()) // Keep only files
-//         .map(|path| {
-//             let content = fs::read_to_string(&path)
-//                 .context(format!("读取模型文件{:?}错误", path.file_name()))?;
-//             let model = serde_json::from_str::<FileModel>(&content).context("解析模型文件错误")?;
-//             // 目前模型文件中一定有children和algorithm字段，无children的模型不另起文件单独存放
-//             if model.children.is_none(){
-//                 Err(anyhow::anyhow!("模型文件{:?}中未找到children字段", path.file_name()))?;
-//             }
-//             if model.algorithm.is_none(){
-//                 Err(anyhow::anyhow!("模型文件{:?}中未找到algorithm字段", path.file_name()))?;
-//             }
-//             Ok((model.name.clone(), model))
-//         }) // Read file content
-//         .collect::<Result<HashMap<_, _>>>()?; // short circuit error handling
-//     // 遍历所有模型及其children，将所有名字放入集合中
-//     let mut names = HashSet::new();
-//     file_models.iter().for_each(|(_name, model)| {
-//         names.insert(model.name.clone());
-//         if let Some(children) = &model.children {
-//             children.iter().for_each(|child| {
-//                 names.insert(child.clone());
-//             });
-//         }
-//     });
-//     // 在原有模型集合的基础上加入叶节点模型
-//     names.iter().for_each(|name| {
-//         if !file_models.contains_key(name) {
-//             file_models.insert(name.clone(), FileModel{name: name.clone(), algorithm: None, children: None});
-//         }
-//     });
-//     let mut ref_counts = file_models.iter().map::<(String, u64), _>(|(name, _model)| {
-//         (name.to_string(), 0)
-//     }).collect::<HashMap<String, u64>>();
-//     file_models.iter().for_each(|(_name, model)| {
-//         if let Some(children) = &model.children {
-//             children.iter().for_each(|child| {
-//                 let count = ref_counts.get(child).expect(format!("child {} does not exist in ref count", child).as_str());
-//                 ref_counts.insert(child.clone(), count + 1);
-//             });
-//         }
-//     });
-//     let result = file_models.into_iter().map::<(String, Model),_>(|(name, model)| {
-//         (name.clone(), Model{name: model.name, algorithm: model.algorithm, children: model.children, ref_count: ref_counts.get(&name).expect("model does not exist in ref count").clone()})
-//     }).collect();
-//     Ok(result)
-// }
+}
 
 // program logic:
 // 1. load all model files from a specified folder into a hashmap, with root as a special element
@@ -182,292 +163,524 @@ fn load_models(file_path: String) -> Result<HashMap<String, Model>>{
 // 4. render side will request children when an element is expanded
 // 5. for each child, need to specify its name and whether it has a child
 
-#[derive(serde::Serialize, serde::Deserialize)]
-struct UpdateNameResponse{
-    new_name: String,
-    requires_update: bool
+fn append_journal(entry: JournalEntry, state: &TauriState) -> Result<(), ModelError>{
+    journal::append(&journal::journal_path(&state.models_file_path), &entry).map_err(ModelError::io)
 }
 
-fn update_dup_name_no_children_backend(old_name: &str, new_name: &str, state: &mut TauriState){
-    // this function is only called when the new name is duplicated, and the model does not have children
-    // the model should snap to the one that originally has this new name
-    // iterate through all the models and replace the children with the new name
-    replace_old_name_no_children(old_name, new_name, &mut state.models);
-    // update the reference count
-    update_reference_count(&mut state.models);
+// 命令结束后调用，无论命令是否成功：失败的修改已被恢复，不会产生事件
+fn emit_changes(app: &tauri::AppHandle, graph: &mut ModelGraph) {
+    let changes = graph.take_events();
+    emit(app, events::NODE_RENAMED, changes.renamed);
+    emit(app, events::CHILDREN_CHANGED, changes.children_changed);
+    emit(app, events::REF_COUNT_CHANGED, changes.ref_count_changed);
+    emit(app, events::ALGORITHM_CHANGED, changes.algorithm_changed);
+    emit(app, events::PINNED_CHANGED, changes.pinned_changed);
 }
 
-fn suggest_new_name_dupe(new_name: &str, models: &HashMap<String, Model>) -> String{
-    let mut new_name = new_name.to_string();
-    while models.get(&new_name).is_some(){
-        new_name = format!("{}（错误：重名）", new_name);
+fn emit<T: serde::Serialize + Clone>(app: &tauri::AppHandle, event: &str, models: Vec<T>) {
+    if models.is_empty() {
+        return;
     }
-    new_name
-}
-fn suggest_new_name_add(models: &HashMap<String, Model>) -> String{
-    let mut new_name = "新节点".to_string();
-    let mut i = 0;
-    while models.get(&new_name).is_some(){
-        i += 1;
-        new_name = format!("新节点{}", i);
+    // 事件只用于刷新显示，发送失败时不影响修改本身
+    if let Err(e) = app.emit_all(event, events::ChangeEvent { models }) {
+        eprintln!("发送事件{}错误：{}", event, e);
     }
-    new_name
-}
-
-fn update_dup_name_has_children_backend(old_name: &str, new_processed_name: &str, state: &mut TauriState){
-    // this function is called when the new name is duplicated, and the model has children
-    // the model will not snap to any existing node because the new name is supposed to be different from any existing ...
-    replace_old_name_has_children(old_name, new_processed_name, &mut state.models);
-    // reference count should not change in this case
-}
-
-fn update_non_dup_name_backend(old_name: &str, new_name: &str, state: &mut TauriState){
-    // the logic should be the same as dup_name_has_children
-    update_dup_name_has_children_backend(old_name, new_name, state);
-}
-
-fn replace_old_name_has_children(old_name: &str, new_processed_name: &str, models: &mut HashMap<String, Model>){
-    // the new processed name should be different from any existing names
-    assert!(models.get(new_processed_name).is_none(), "model {} already exists", new_processed_name);
-    // get the model corresponding to the old name
-    println!("模型{}被移除", old_name);
-    let mut model = models.remove(old_name).expect(format!("model {} does not exist", old_name).as_str());
-    model.name = new_processed_name.to_string();
-    println!("模型{}被加入", new_processed_name);
-    models.insert(new_processed_name.to_string(), model);
-    // modify children to have the new name
-    replace_old_name_no_children(old_name, new_processed_name, models);
-}
-
-fn replace_old_name_no_children(old_name: &str, new_name: &str, models: &mut HashMap<String, Model>){
-    models.iter_mut().for_each(|(_name, model)|{
-        model.children.iter_mut().for_each(|children|{
-            children.iter_mut().for_each(|child|{
-                if child == old_name{
-                    *child = new_name.to_string();
-                }
-            });
-        });
-    });
-}
-fn update_reference_count(models: &mut HashMap<String, Model>){
-    let mut ref_counts = models.iter().map::<(String, u64), _>(|(name, _model)| {
-        (name.to_string(), 0)
-    }).collect::<HashMap<String, u64>>();
-    models.iter().for_each(|(_name, model)| {
-        if let Some(children) = &model.children {
-            children.iter().for_each(|child| {
-                let count = ref_counts.get(child).expect(format!("child {} does not exist in ref count", child).as_str());
-                ref_counts.insert(child.clone(), count + 1);
-            });
-        }
-    });
-    models.iter_mut().for_each(|(name, model)|{
-        model.ref_count = ref_counts.get(name).expect(format!("model {} does not exist in ref count", name).as_str()).clone();
-    });
 }
 
+#[tauri::command]
+fn update_node_name(id: ModelId, new_name: &str, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<UpdateNameResponse, ModelError> {
+    println!("update_node called with id: {} and new name: {}", id, new_name);
+    let mut state = state.lock().unwrap();
+    append_journal(JournalEntry::UpdateNodeName { id, new_name: new_name.to_string() }, &state)?;
+    let result = state.graph.rename(id, new_name);
+    emit_changes(&app, &mut state.graph);
+    result
+}
 
+// 预览或批量重命名模型，作为一次修改撤销；有名称冲突时不修改，由前端展示冲突
 #[tauri::command]
-fn update_node_name(name: &str, new_name: &str, state: tauri::State<Mutex<TauriState>>) -> UpdateNameResponse {
-    println!("update_node called with current name: {} and new name: {}", name, new_name);
-    // 1. if the new node name is not duplicated, then simply apply (no trigger update, the same modified name)
-      // 2. if the node name is duplicated, then check:
-      // if the node itself does not have children, then accept the change, update reference count, and: (reference count: needs to be updated)
-      //    if the nodes with the same name have children, then add all the children to the renamed node (updated)
-      //    if the nodes with the same name do not have children, do nothing (reference count updated)
-      // if the node has children, then rename the node to something else (different modified name, no update)
+fn batch_rename(pattern: &str, replacement: &str, dry_run: bool, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<BatchRenameReport, ModelError> {
+    println!("Rust: batch_rename called with pattern: {:?}, replacement: {:?}, dry_run: {}", pattern, replacement, dry_run);
     let mut state = state.lock().unwrap();
-    if name == new_name{
-        return UpdateNameResponse{new_name: new_name.to_string(), requires_update: false};
-    }
-    // check for duplicate names
-    match state.models.get(new_name){
-        Some(_) =>{
-            // new name is duplicated with old names
-            match state.models.get(name){
-                Some(model)=>{
-                    // old name exists
-                    match &model.children{
-                        Some(_) => {
-                            let new_processed_name = suggest_new_name_dupe(new_name, &state.models);
-                            println!("新名称重名，模型{}有子节点，重命名为\"{}\"，更新所有节点", name, new_processed_name);
-                            // 虽然局部看起来不需要更新，但是可能有其他父节点有这个节点，所以需要更新
-                            update_dup_name_has_children_backend(name, &new_processed_name, &mut state);
-                            UpdateNameResponse{new_name: new_processed_name, requires_update: true}
-                        }
-                        None => {
-                            println!("新名称重名，模型{}无子节点，重命名为\"{}\"，更新所有节点", name, new_name);
-                            // 后端搜索所有节点，将原名为name的节点重命名为new_name，更新reference count
-                            update_dup_name_no_children_backend(name, new_name, &mut state);
-                            UpdateNameResponse{new_name: new_name.to_string(), requires_update: true}
-                        }
-                    }
-                },
-                None =>{
-                    eprintln!("前后端失去同步：未找到原名为\"{}\"的模型", name);
-                    exit(1);
-                }
-            }
-        },
-        None =>{
-            println!("模型{}重命名为\"{}\"，更新所有节点", name, new_name);
-            // 虽然局部看起来不需要更新，但是可能有其他父节点有这个节点，所以需要更新
-            update_non_dup_name_backend(name, new_name, &mut state);
-            UpdateNameResponse{new_name: new_name.to_string(), requires_update: true}
-        }
+    let report = state.graph.plan_batch_rename(pattern, replacement)?;
+    if dry_run || report.renames.is_empty() || !report.collisions.is_empty(){
+        return Ok(report);
     }
+    append_journal(JournalEntry::BatchRename { pattern: pattern.to_string(), replacement: replacement.to_string() }, &state)?;
+    let result = state.graph.batch_rename(pattern, replacement);
+    emit_changes(&app, &mut state.graph);
+    result
 }
 
-fn add_node_to_parent(parent_name: &str, new_name: &str, models: &mut HashMap<String, Model>){
-    // asser new name does not exist in models
-    assert!(models.get(new_name).is_none());
-    // add new name to models with no children or algorithm
-    models.insert(new_name.to_string(), Model{name: new_name.to_string(), algorithm: None, children: None, ref_count: 0});
-    let parent = models.get_mut(parent_name).expect(format!("parent {} does not exist", parent_name).as_str());
-    let children = parent.children.as_mut().expect(format!("parent {} does not have children", parent_name).as_str());
-    children.push(new_name.to_string());
-    // update reference counts
-    update_reference_count(models);
+// 返回新模型的ID
+#[tauri::command]
+fn add_node(parent_id: ModelId, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<ModelId, ModelError> {
+    let mut state = state.lock().unwrap();
+    println!("Rust: add_node called with parent_id: {}", parent_id);
+    let new_name = state.graph.new_node_name();
+    let new_id = state.graph.next_id();
+    append_journal(JournalEntry::AddNode { parent_id, new_id, new_name: new_name.clone() }, &state)?;
+    let result = state.graph.add_node(parent_id, None, new_id, &new_name);
+    emit_changes(&app, &mut state.graph);
+    result?;
+    Ok(new_id)
 }
 
+// 在父节点children的index处插入新模型，返回新模型的ID
 #[tauri::command]
-fn add_node(parent_name: &str, state: tauri::State<Mutex<TauriState>>) -> String {
+fn insert_node_at(parent_id: ModelId, index: usize, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<ModelId, ModelError> {
     let mut state = state.lock().unwrap();
-    println!("Rust: add_node called with parent_name: {}", parent_name);
-    let new_name = suggest_new_name_add(&mut state.models);
-    add_node_to_parent(parent_name, &new_name, &mut state.models);
-    new_name
+    println!("Rust: insert_node_at called with parent_id: {}, index: {}", parent_id, index);
+    let new_name = state.graph.new_node_name();
+    let new_id = state.graph.next_id();
+    append_journal(JournalEntry::InsertNodeAt { parent_id, index, new_id, new_name: new_name.clone() }, &state)?;
+    let result = state.graph.add_node(parent_id, Some(index), new_id, &new_name);
+    emit_changes(&app, &mut state.graph);
+    result?;
+    Ok(new_id)
 }
 
-fn remove_node_from_parent(parent_name: &str, name: &str, models: &mut HashMap<String, Model>){
-    // remove the node from the models
-    let model = models.get(name).expect(format!("model {} does not exist", name).as_str());
-    if model.ref_count == 1{
-        models.remove(name).expect(format!("model {} does not exist", name).as_str());
-    }
-    // remove the node from the parent
-    let parent = models.get_mut(parent_name).expect(format!("parent {} does not exist", parent_name).as_str());
-    let children = parent.children.as_mut().expect(format!("parent {} does not have children", parent_name).as_str());
-    children.retain(|child| child != name);
-    // update reference counts
-    update_reference_count(models);
+// 调整子节点的顺序，顺序影响显示以及按位置引用子节点的算法
+#[tauri::command]
+fn move_child(parent_id: ModelId, id: ModelId, new_index: usize, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    println!("Rust: move_child called with parent_id: {}, id: {}, new_index: {}", parent_id, id, new_index);
+    let mut state = state.lock().unwrap();
+    append_journal(JournalEntry::MoveChild { parent_id, id, new_index }, &state)?;
+    let result = state.graph.move_child(parent_id, id, new_index);
+    emit_changes(&app, &mut state.graph);
+    result
+}
+
+#[tauri::command]
+fn delete_node(parent_id: ModelId, id: ModelId, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    let mut state = state.lock().unwrap();
+    println!("delete_node called with id: {}", id);
+    append_journal(JournalEntry::DeleteNode { parent_id, id }, &state)?;
+    let result = state.graph.delete_node(parent_id, id);
+    emit_changes(&app, &mut state.graph);
+    result
+}
+
+#[derive(serde::Serialize, Clone)]
+struct DeletePreview {
+    // 删除后不再被引用、将被一并删除的模型，按从上到下的顺序；为空时只是从父节点中移除
+    removed: Vec<Node>,
+}
+
+// 预览delete_node将删除的模型，不修改模型
+#[tauri::command]
+fn preview_delete_node(parent_id: ModelId, id: ModelId, state: tauri::State<Mutex<TauriState>>) -> Result<DeletePreview, ModelError> {
+    println!("Rust: preview_delete_node called with parent_id: {}, id: {}", parent_id, id);
+    let state = state.lock().unwrap();
+    let removed = state.graph.preview_delete_node(parent_id, id)?;
+    Ok(DeletePreview { removed })
+}
+
+// 将已有的模型加入另一个父节点，两个父节点共享同一个模型
+#[tauri::command]
+fn link_existing_node(parent_id: ModelId, id: ModelId, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    println!("Rust: link_existing_node called with parent_id: {}, id: {}", parent_id, id);
+    let mut state = state.lock().unwrap();
+    append_journal(JournalEntry::LinkExistingNode { parent_id, id }, &state)?;
+    let result = state.graph.link_existing_node(parent_id, id);
+    emit_changes(&app, &mut state.graph);
+    result
+}
+
+// 复制模型及其下的所有模型，加入new_parent，返回副本的ID
+#[tauri::command]
+fn deep_copy_subtree(id: ModelId, new_parent_id: ModelId, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<ModelId, ModelError> {
+    println!("Rust: deep_copy_subtree called with id: {}, new_parent_id: {}", id, new_parent_id);
+    let mut state = state.lock().unwrap();
+    let new_id = state.graph.next_id();
+    append_journal(JournalEntry::DeepCopySubtree { id, new_parent_id, new_id }, &state)?;
+    let result = state.graph.deep_copy_subtree(id, new_parent_id, new_id);
+    emit_changes(&app, &mut state.graph);
+    result?;
+    Ok(new_id)
+}
+
+// 将模型从一个父节点移到另一个父节点，只移动一次出现
+#[tauri::command]
+fn move_node(old_parent_id: ModelId, new_parent_id: ModelId, id: ModelId, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    println!("Rust: move_node called with old_parent_id: {}, new_parent_id: {}, id: {}", old_parent_id, new_parent_id, id);
+    let mut state = state.lock().unwrap();
+    append_journal(JournalEntry::MoveNode { old_parent_id, new_parent_id, id }, &state)?;
+    let result = state.graph.move_node(old_parent_id, new_parent_id, id);
+    emit_changes(&app, &mut state.graph);
+    result
 }
 
+// 将源模型合并到目标模型，所有引用源模型的地方改为引用目标，作为一次修改撤销
 #[tauri::command]
-fn delete_node(parent_name: &str, name: &str, state: tauri::State<Mutex<TauriState>>) {
+fn merge_nodes(source_id: ModelId, target_id: ModelId, strategy: MergeStrategy, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<MergeReport, ModelError> {
+    println!("Rust: merge_nodes called with source_id: {}, target_id: {}, strategy: {:?}", source_id, target_id, strategy);
     let mut state = state.lock().unwrap();
-    println!("delete_node called with name: {}", name);
-    // this is tricky because we should only delete the node inside its parent. If it is referenced by other nodes, we should not remove it entirely from the models
-    // if its reference count is 1, then we can remove it entirely
-    // if its reference count is more than 1, then we should only remove it from its parent
-    remove_node_from_parent(parent_name, name, &mut state.models);
+    append_journal(JournalEntry::MergeNodes { source_id, target_id, strategy }, &state)?;
+    let result = state.graph.merge_nodes(source_id, target_id, strategy);
+    emit_changes(&app, &mut state.graph);
+    result
 }
 
 #[tauri::command]
-fn query_root_name(state: tauri::State<Mutex<TauriState>>) -> String {
+fn query_root(state: tauri::State<Mutex<TauriState>>) -> Result<ModelId, ModelError> {
     let state = state.lock().unwrap();
-    state.root_name.clone()
+    let root_id = state.graph.root_id();
+    state.graph.model(root_id)?;
+    Ok(root_id)
 }
 
+// 所有没有父节点的模型，以及被设为入口的模型
 #[tauri::command]
-fn query_node(name: &str, state: tauri::State<Mutex<TauriState>>) -> Node {
-    println!("Rust: query_node called with name: {}", name);
+fn query_roots(state: tauri::State<Mutex<TauriState>>) -> Result<Vec<Node>, ModelError> {
     let state = state.lock().unwrap();
-    let model = match state.models.get(name) {
-        Some(model) => model,
-        None => {
-            eprintln!("query node 错误：未找到模型{}", name);
-            exit(1);
+    Ok(state.graph.roots())
+}
+
+// 切换前端显示的根节点，不属于对模型的修改，不记录日志和撤销
+#[tauri::command]
+fn set_root(id: ModelId, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    println!("Rust: set_root called with id: {}", id);
+    let mut state = state.lock().unwrap();
+    state.graph.set_root(id)
+}
+
+#[tauri::command]
+fn add_root(id: ModelId, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    println!("Rust: add_root called with id: {}", id);
+    let mut state = state.lock().unwrap();
+    append_journal(JournalEntry::AddRoot { id }, &state)?;
+    let result = state.graph.set_pinned(id, true);
+    emit_changes(&app, &mut state.graph);
+    result
+}
+
+// 没有父节点的模型取消入口后仍是根节点
+#[tauri::command]
+fn remove_root(id: ModelId, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    println!("Rust: remove_root called with id: {}", id);
+    let mut state = state.lock().unwrap();
+    append_journal(JournalEntry::RemoveRoot { id }, &state)?;
+    let result = state.graph.set_pinned(id, false);
+    emit_changes(&app, &mut state.graph);
+    result
+}
+
+// 设置是否要求模型名称唯一，属于模型文件的设置，随模型保存
+#[tauri::command]
+fn set_unique_names(unique_names: bool, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    println!("Rust: set_unique_names called with unique_names: {}", unique_names);
+    let mut state = state.lock().unwrap();
+    append_journal(JournalEntry::SetUniqueNames { unique_names }, &state)?;
+    let result = state.graph.set_unique_names(unique_names);
+    emit_changes(&app, &mut state.graph);
+    result
+}
+
+#[tauri::command]
+fn query_node(id: ModelId, state: tauri::State<Mutex<TauriState>>) -> Result<Node, ModelError> {
+    println!("Rust: query_node called with id: {}", id);
+    let state = state.lock().unwrap();
+    let model = match state.graph.model(id) {
+        Ok(model) => model,
+        Err(e) => {
+            eprintln!("query node 错误：未找到ID为{}的模型", id);
+            return Err(e);
         }
     };
     let has_children = model.children.is_some();
     let has_algorithm = model.algorithm.is_some();
     if has_children != has_algorithm {
-        eprintln!("错误：模型{}状态冲突：{}子节点但{}算法声明", name, if has_children { "有" } else { "无" }, if has_algorithm { "有" } else { "无" });
-        exit(1);
+        let message = format!("模型{}状态冲突：{}子节点但{}算法声明", model.name, if has_children { "有" } else { "无" }, if has_algorithm { "有" } else { "无" });
+        eprintln!("错误：{}", message);
+        return Err(ModelError::invariant_violation(model, message));
     }
-    Node {
-        name: model.name.clone(),
-        ref_count: 0,
-        has_children,
-    }    
+    Ok(Node::from(model))
 }
 #[tauri::command]
-fn query_children(parent_name: &str, state: tauri::State<Mutex<TauriState>>) -> Vec<String> {
-    println!("Rust: query_children called with parent_name: {}", parent_name);
+fn query_children(parent_id: ModelId, state: tauri::State<Mutex<TauriState>>) -> Result<Vec<ModelId>, ModelError> {
+    println!("Rust: query_children called with parent_id: {}", parent_id);
     let state = state.lock().unwrap();
-    let model = match state.models.get(parent_name) {
-        Some(model) => model,
-        None => {
-            eprintln!("query children 错误：未找到模型{}", parent_name);
-            exit(1);
+    let model = match state.graph.model(parent_id) {
+        Ok(model) => model,
+        Err(e) => {
+            eprintln!("query children 错误：未找到ID为{}的模型", parent_id);
+            return Err(e);
         }
     };
     match &model.children {
-        Some(children) => children.clone(),
+        Some(children) => Ok(children.clone()),
         None => {
-            eprintln!("错误：模型{}无子节点", parent_name);
-            exit(1);
+            eprintln!("错误：模型{}无子节点", model.name);
+            Err(ModelError::not_a_composite(model))
         }
     }
 }
 #[tauri::command]
-fn query_algorithm(parent_name: &str, state: tauri::State<Mutex<TauriState>>) -> String {
-    println!("Rust: query_algorithm called with parent_name: {}", parent_name);
+fn query_algorithm(id: ModelId, state: tauri::State<Mutex<TauriState>>) -> Result<String, ModelError> {
+    println!("Rust: query_algorithm called with id: {}", id);
     let state = state.lock().unwrap();
-    let model = match state.models.get(parent_name) {
-        Some(model) => model,
-        None => {
-            eprintln!("query algorithm 错误：未找到模型{}", parent_name);
-            exit(1);
+    let model = match state.graph.model(id) {
+        Ok(model) => model,
+        Err(e) => {
+            eprintln!("query algorithm 错误：未找到ID为{}的模型", id);
+            return Err(e);
         }
     };
     match &model.algorithm {
-        Some(algorithm) => algorithm.clone(),
+        Some(algorithm) => Ok(algorithm.clone()),
         None => {
-            eprintln!("错误：模型{}无算法", parent_name);
-            exit(1);
+            eprintln!("错误：模型{}无算法", model.name);
+            Err(ModelError::not_a_composite(model))
         }
     }
 }
 
 #[tauri::command]
-fn query_ref_count(name: &str, state: tauri::State<Mutex<TauriState>>) -> u64 {
-    println!("Rust: query_ref_count called with name: {}", name);
+fn query_ref_count(id: ModelId, state: tauri::State<Mutex<TauriState>>) -> Result<u64, ModelError> {
+    println!("Rust: query_ref_count called with id: {}", id);
     let state = state.lock().unwrap();
-    match state.models.get(name) {
-        Some(model) => model.ref_count,
-        None => {
-            eprintln!("ref count 警告：可能被丢弃的模型{}", name);
-            0
+    match state.graph.model(id) {
+        Ok(model) => Ok(model.ref_count),
+        Err(e) => {
+            eprintln!("ref count 错误：未找到ID为{}的模型", id);
+            Err(e)
         }
     }
 }
+// 引用该模型的所有父节点，修改共享的模型前用于确认影响范围
+#[tauri::command]
+fn query_parents(id: ModelId, state: tauri::State<Mutex<TauriState>>) -> Result<Vec<Node>, ModelError> {
+    println!("Rust: query_parents called with id: {}", id);
+    let state = state.lock().unwrap();
+    state.graph.model(id)?;
+    Ok(state.graph.parents(id).into_iter().filter_map(|parent| state.graph.models().get(&parent)).map(Node::from).collect())
+}
+
+// 从所有顶层模型到该模型的路径，即该模型在哪些地方被使用
+#[tauri::command]
+fn query_usage_paths(id: ModelId, state: tauri::State<Mutex<TauriState>>) -> Result<UsagePaths, ModelError> {
+    println!("Rust: query_usage_paths called with id: {}", id);
+    let state = state.lock().unwrap();
+    state.graph.usage_paths(id, MAX_USAGE_PATHS)
+}
+
+// 按名称、拼音、首字母或正则表达式查找模型，mode为None时按Fuzzy模式查找，每个结果附带从根节点到该模型的路径
+#[tauri::command]
+fn search_models(query: &str, mode: Option<SearchMode>, state: tauri::State<Mutex<TauriState>>) -> Result<SearchResults, ModelError> {
+    println!("Rust: search_models called with query: {:?}, mode: {:?}", query, mode);
+    let state = state.lock().unwrap();
+    state.graph.search(query, mode.unwrap_or(SearchMode::Fuzzy), MAX_SEARCH_RESULTS)
+}
+
 #[tauri::command]
-fn toggle_has_children(name: &str, state: tauri::State<Mutex<TauriState>>) {
+fn toggle_has_children(id: ModelId, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
     let mut state = state.lock().unwrap();
-    let model = state.models.get_mut(name).expect(format!("model {} does not exist", name).as_str());
-    match model.children{
-        Some(_)=>{
-            assert!(model.algorithm.is_some());
-            model.children = None;
-            model.algorithm = None;
-        }
-        None=>{
-            model.children = Some(vec![]);
-            model.algorithm = Some("未定义算法".to_string());
-        }
+    append_journal(JournalEntry::ToggleHasChildren { id }, &state)?;
+    let result = state.graph.toggle_has_children(id);
+    emit_changes(&app, &mut state.graph);
+    result
+}
+// 预览将组合模型改为叶节点时一并删除的模型，不修改模型
+#[tauri::command]
+fn preview_toggle_has_children(id: ModelId, state: tauri::State<Mutex<TauriState>>) -> Result<DeletePreview, ModelError> {
+    println!("Rust: preview_toggle_has_children called with id: {}", id);
+    let state = state.lock().unwrap();
+    let removed = state.graph.preview_toggle_has_children(id)?;
+    Ok(DeletePreview { removed })
+}
+
+#[tauri::command]
+fn update_algorithm(id: ModelId, algorithm: &str, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    let mut state = state.lock().unwrap();
+    append_journal(JournalEntry::UpdateAlgorithm { id, algorithm: algorithm.to_string() }, &state)?;
+    let result = state.graph.update_algorithm(id, algorithm);
+    emit_changes(&app, &mut state.graph);
+    result
+}
+
+// 用给定的子节点取值计算模型的算法，用于编辑算法时试算，values以子节点名称为键
+#[tauri::command]
+fn evaluate_algorithm(id: ModelId, values: HashMap<String, f64>, state: tauri::State<Mutex<TauriState>>) -> Result<f64, ModelError> {
+    println!("Rust: evaluate_algorithm called with id: {}", id);
+    let state = state.lock().unwrap();
+    state.graph.evaluate_algorithm(id, &values)
+}
+
+// 根据叶节点的测量值计算整棵树，未指定root_id时从当前根节点开始
+#[tauri::command]
+fn evaluate(leaf_values: HashMap<ModelId, f64>, root_id: Option<ModelId>, state: tauri::State<Mutex<TauriState>>) -> Result<Evaluation, ModelError> {
+    let state = state.lock().unwrap();
+    println!("Rust: evaluate called with root_id: {}, {}个叶节点取值", root_id.unwrap_or(state.graph.root_id()), leaf_values.len());
+    state.graph.evaluate(&leaf_values, root_id)
+}
+
+#[tauri::command]
+fn undo(app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<Option<String>, ModelError> {
+    let mut state = state.lock().unwrap();
+    // 没有可撤销的修改时不记录，重放时这样的记录会报错
+    if state.graph.can_undo() {
+        append_journal(JournalEntry::Undo, &state)?;
+    }
+    let description = state.graph.undo();
+    println!("Rust: undo called, 撤销：{:?}", description);
+    emit_changes(&app, &mut state.graph);
+    Ok(description)
+}
+
+#[tauri::command]
+fn redo(app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<Option<String>, ModelError> {
+    let mut state = state.lock().unwrap();
+    if state.graph.can_redo() {
+        append_journal(JournalEntry::Redo, &state)?;
+    }
+    let description = state.graph.redo();
+    println!("Rust: redo called, 重做：{:?}", description);
+    emit_changes(&app, &mut state.graph);
+    Ok(description)
+}
+
+#[tauri::command]
+fn save_models(state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    let mut state = state.lock().unwrap();
+    println!("Rust: save_models called, file path: {}", state.models_file_path);
+    save(&mut state)
+}
+
+// 没有未保存的修改时只清空日志
+fn save(state: &mut TauriState) -> Result<(), ModelError> {
+    // 保存会清空日志，尚未决定恢复或丢弃的修改将无法再恢复
+    if !state.pending_journal.is_empty() {
+        return Err(ModelError::pending_journal(state.pending_journal.len()));
+    }
+    if !state.graph.is_modified() {
+        return journal::clear(&journal::journal_path(&state.models_file_path)).map_err(ModelError::io);
+    }
+    // 不再写入旧版的模型目录
+    if Path::new(&state.models_file_path).is_dir() {
+        return Err(ModelError::io(anyhow::anyhow!(
+            "{:?}是旧版的模型目录，请使用另存为或迁移保存为单个模型文件", state.models_file_path
+        )));
+    }
+    let file_path = state.models_file_path.clone();
+    state.graph.save(&file_path).map_err(ModelError::io)?;
+    // 修改已写入模型文件，日志中的记录不再需要，撤销记录已随保存清空
+    journal::clear(&journal::journal_path(&state.models_file_path)).map_err(ModelError::io)
+}
+
+#[tauri::command]
+fn save_models_as(file_path: &str, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    let mut state = state.lock().unwrap();
+    println!("Rust: save_models_as called with file path: {}", file_path);
+    if !state.pending_journal.is_empty() {
+        return Err(ModelError::pending_journal(state.pending_journal.len()));
     }
+    state.graph.save(file_path).map_err(ModelError::io)?;
+    journal::clear(&journal::journal_path(&state.models_file_path)).map_err(ModelError::io)?;
+    // 之后的保存与日志都对应新文件
+    state.models_file_path = file_path.to_string();
+    journal::clear(&journal::journal_path(&state.models_file_path)).map_err(ModelError::io)
+}
+
+#[tauri::command]
+fn query_pending_journal(state: tauri::State<Mutex<TauriState>>) -> Result<Vec<JournalEntry>, ModelError> {
+    let state = state.lock().unwrap();
+    Ok(state.pending_journal.clone())
 }
+
+#[tauri::command]
+fn replay_journal(app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<usize, ModelError> {
+    let mut state = state.lock().unwrap();
+    let entries = std::mem::take(&mut state.pending_journal);
+    println!("Rust: replay_journal called, 重放{}条修改", entries.len());
+    // 重放的记录仍保留在日志中，直到下一次保存
+    // 记录在执行前写入日志，执行失败的记录当时也未生效，重放时跳过即可
+    let mut replayed = 0;
+    entries.iter().for_each(|entry| match state.graph.apply_journal_entry(entry) {
+        Ok(()) => replayed += 1,
+        Err(e) => eprintln!("重放日志记录{:?}失败，已跳过：{}", entry, e),
+    });
+    emit_changes(&app, &mut state.graph);
+    Ok(replayed)
+}
+
 #[tauri::command]
-fn update_algorithm(name: &str, algorithm: &str, state: tauri::State<Mutex<TauriState>>) {
+fn discard_journal(state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
     let mut state = state.lock().unwrap();
-    let model = state.models.get_mut(name).expect(format!("model {} does not exist", name).as_str());
-    model.algorithm = Some(algorithm.to_string());
+    println!("Rust: discard_journal called, 丢弃{}条修改", state.pending_journal.len());
+    state.pending_journal.clear();
+    journal::clear(&journal::journal_path(&state.models_file_path)).map_err(ModelError::io)
+}
+
+// 返回模型图中所有环的路径
+#[tauri::command]
+fn validate_graph(state: tauri::State<Mutex<TauriState>>) -> Result<Vec<Vec<ModelId>>, ModelError> {
+    let state = state.lock().unwrap();
+    Ok(graph::validate_graph(state.graph.models()))
+}
+
+// 检查模型中的问题，包括读取模型文件时发现的问题
+#[tauri::command]
+fn lint_models(state: tauri::State<Mutex<TauriState>>) -> Result<Vec<Diagnostic>, ModelError> {
+    let state = state.lock().unwrap();
+    let diagnostics = state.graph.diagnostics();
+    println!("Rust: lint_models called, 发现{}个问题", diagnostics.len());
+    Ok(diagnostics)
+}
+
+// 将模型图导出为DOT或Mermaid文本，未指定root_id时从当前根节点开始，depth为None时不限层数
+#[tauri::command]
+fn export_graph(format: DiagramFormat, root_id: Option<ModelId>, depth: Option<usize>, state: tauri::State<Mutex<TauriState>>) -> Result<String, ModelError> {
+    println!("Rust: export_graph called with format: {:?}, root_id: {:?}, depth: {:?}", format, root_id, depth);
+    let state = state.lock().unwrap();
+    state.graph.export_graph(format, root_id, depth)
+}
+
+// 将模型导出为边表CSV或大纲，供在表格中编辑，不改变当前的模型文件
+#[tauri::command]
+fn export_flat(format: FlatFormat, file_path: &str, state: tauri::State<Mutex<TauriState>>) -> Result<(), ModelError> {
+    println!("Rust: export_flat called with format: {:?}, file path: {}", format, file_path);
+    let state = state.lock().unwrap();
+    flat::write_file(format, file_path, &state.graph.file_models()?)
+}
+
+// 导入边表CSV或大纲，替换当前的模型；dry_run时只返回与当前模型的差异，由前端确认后再导入
+#[tauri::command]
+fn import_flat(format: FlatFormat, file_path: &str, dry_run: bool, app: tauri::AppHandle, state: tauri::State<Mutex<TauriState>>) -> Result<ImportReport, ModelError> {
+    println!("Rust: import_flat called with format: {:?}, file path: {}, dry_run: {}", format, file_path, dry_run);
+    let file_models = flat::read_file(format, file_path)?;
+    let mut state = state.lock().unwrap();
+    let report = state.graph.plan_import(file_models.clone())?;
+    if dry_run || report.is_empty() {
+        return Ok(report);
+    }
+    append_journal(JournalEntry::ImportModels { models: file_models.clone() }, &state)?;
+    let result = state.graph.import(file_models);
+    emit_changes(&app, &mut state.graph);
+    result
+}
+
+// 将旧版的模型目录转换为单个模型文件，不改变当前打开的模型
+#[tauri::command]
+fn migrate_legacy_models(dir: &str, file_path: &str) -> Result<legacy::MigrationReport, ModelError> {
+    println!("Rust: migrate_legacy_models called with dir: {}, file path: {}", dir, file_path);
+    let report = legacy::migrate(dir, file_path)?;
+    report.diagnostics.iter().for_each(print_diagnostic);
+    Ok(report)
+}
+
+// 打开另一个模型文件，替换当前的模型、撤销记录与日志，未保存的修改仍保留在原文件的日志中
+// 返回新的根节点
+#[tauri::command]
+fn open_model_file(file_path: String, root_name: Option<String>, state: tauri::State<Mutex<TauriState>>) -> Result<ModelId, ModelError> {
+    println!("Rust: open_model_file called with file path: {}, root name: {:?}", file_path, root_name);
+    let new_state = TauriState::open(file_path, root_name)?;
+    let mut state = state.lock().unwrap();
+    *state = new_state;
+    Ok(state.graph.root_id())
 }
 
 #[tauri::command]
 fn log(message: String){
     println!("{}", message);
-}
\ No newline at end of file
+}
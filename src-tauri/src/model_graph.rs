@@ -0,0 +1,1030 @@
+// 模型图：模型及其反向索引、撤销记录，所有对模型的修改都经过这里
+// 每次修改作为一次可撤销的编辑记录，失败或会形成环时恢复修改前的状态
+use crate::algorithm::{self, AlgorithmError};
+use crate::diagram::{self, DiagramFormat};
+use crate::error::ModelError;
+use crate::evaluate::{self, Evaluation};
+use crate::events::{self, ChangeEvents, ChangeSet};
+use crate::graph;
+use crate::history::{record_edit, History};
+use crate::index::ParentIndex;
+use crate::journal::JournalEntry;
+use crate::lint::{self, Diagnostic};
+use crate::search::{self, SearchMode, SearchResults};
+use crate::schema::FileModel;
+use crate::{
+    build_models, children_labels, composite_children, detect_root_id, find_by_name, load_models, model_label, write_models, LoadedModels, Model,
+    ModelId, Node, UsagePath, UsagePaths,
+};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct UpdateNameResponse{
+    // 合并到同名模型时为该模型的ID
+    pub id: ModelId,
+    pub new_name: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PlannedRename {
+    pub id: ModelId,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+// 批量重命名后同名的一组模型，其中至少有一个被重命名；names为这些模型当前的名称
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct NameCollision {
+    pub name: String,
+    pub ids: Vec<ModelId>,
+    pub names: Vec<String>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct BatchRenameReport {
+    // 按原名称排序
+    pub renames: Vec<PlannedRename>,
+    // 要求名称唯一时会触发重名合并的名称，有冲突时不做任何修改
+    pub collisions: Vec<NameCollision>,
+    pub applied: bool,
+}
+
+// 合并模型时如何处理双方的子节点和算法
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MergeStrategy {
+    // 保留目标的子节点，再加入源模型中目标没有的子节点；目标的算法未确定时改用源模型的算法
+    Union,
+    // 以源模型的子节点和算法替换目标的
+    Replace,
+}
+
+// 导入前后内容不同的模型，子节点以名称表示；None表示没有子节点或算法
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ModelDiff {
+    pub name: String,
+    pub old_children: Option<Vec<String>>,
+    pub new_children: Option<Vec<String>>,
+    pub old_algorithm: Option<String>,
+    pub new_algorithm: Option<String>,
+}
+
+// 导入与当前模型的差异，模型按名称对应，均按名称排序
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ImportReport {
+    pub added: Vec<String>,
+    // 导入的内容中没有、导入后被删除的模型
+    pub removed: Vec<String>,
+    pub changed: Vec<ModelDiff>,
+    pub applied: bool,
+}
+
+impl ImportReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct MergeReport {
+    // 合并后保留的模型
+    pub target: Node,
+    // 被合并而删除的模型，为合并前的状态
+    pub source: Node,
+    // 原来引用源模型、改为引用目标的父节点
+    pub relinked_parents: Vec<Node>,
+    // 从源模型并入目标的子节点
+    pub added_children: Vec<Node>,
+    // 不再是目标的子节点的模型，为合并前的状态
+    pub dropped_children: Vec<Node>,
+    // 合并后不再被引用而被删除的模型，为合并前的状态
+    pub removed: Vec<Node>,
+    pub algorithm: Option<String>,
+    // 双方的算法都已确定且不同时，未被采用的算法
+    pub discarded_algorithm: Option<String>,
+}
+
+// 内存中的模型图，字段只在库内修改，以保证反向索引、撤销记录与模型同步
+#[derive(Debug, Clone)]
+pub struct ModelGraph {
+    pub(crate) models: HashMap<ModelId, Model>,
+    // 子节点 → 父节点的反向索引，与models同步修改
+    pub(crate) parents: ParentIndex,
+    pub(crate) root_id: ModelId,
+    // 下一个新模型的ID，删除的模型的ID不会被重新使用
+    pub(crate) next_id: ModelId,
+    // 为true时模型名称不能重复，重命名为已有名称时合并或加后缀
+    pub(crate) unique_names: bool,
+    pub(crate) history: History,
+    // 上次读取或保存后是否修改过，撤销到保存时的状态也算作修改
+    pub(crate) modified: bool,
+    // 读取模型文件时发现的问题（如重名模型），读入后已无法从models中发现，保存后失效
+    pub(crate) file_diagnostics: Vec<Diagnostic>,
+    // 上次取出事件以来改变了的模型
+    pub(crate) changes: ChangeSet,
+}
+
+impl ModelGraph {
+    // 读取模型文件或旧版的模型目录，root_name为None时自动推断根节点
+    pub fn load(file_path: &str, root_name: Option<&str>) -> Result<ModelGraph, ModelError> {
+        ModelGraph::from_loaded(load_models(file_path)?, root_name)
+    }
+
+    pub fn from_loaded(loaded: LoadedModels, root_name: Option<&str>) -> Result<ModelGraph, ModelError> {
+        let LoadedModels { models, parents, next_id, unique_names, diagnostics } = loaded;
+        let root_id = match root_name {
+            Some(root_name) => match find_by_name(root_name, &models).as_slice() {
+                [root_id] => *root_id,
+                [] => return Err(ModelError::invalid_root(vec![], format!("未找到根节点{}", root_name))),
+                ids => return Err(ModelError::invalid_root(ids.to_vec(), format!("有{}个模型名为{}，无法确定根节点", ids.len(), root_name))),
+            },
+            None => detect_root_id(&models)?,
+        };
+        Ok(ModelGraph {
+            models,
+            parents,
+            root_id,
+            next_id,
+            unique_names,
+            history: History::default(),
+            modified: false,
+            file_diagnostics: diagnostics,
+            changes: ChangeSet::default(),
+        })
+    }
+
+    // 由版本1格式的模型建立模型图，自动推断根节点
+    pub fn from_file_models(file_models: Vec<FileModel>) -> Result<ModelGraph, ModelError> {
+        ModelGraph::from_loaded(build_models(file_models)?, None)
+    }
+
+    // 写入模型文件并清空撤销记录：日志在保存后清空，崩溃恢复时从保存的文件重放，
+    // 保存前的修改不在日志中，之后的撤销无法重放
+    pub fn save(&mut self, file_path: &str) -> Result<()> {
+        write_models(file_path, &self.models, self.next_id, self.unique_names)?;
+        self.history = History::default();
+        self.modified = false;
+        // 保存的文件中每个模型只出现一次
+        self.file_diagnostics.clear();
+        Ok(())
+    }
+
+    // 是否有未保存的修改
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn models(&self) -> &HashMap<ModelId, Model> {
+        &self.models
+    }
+
+    pub fn model(&self, id: ModelId) -> Result<&Model, ModelError> {
+        self.models.get(&id).ok_or_else(|| ModelError::not_found(id))
+    }
+
+    pub fn root_id(&self) -> ModelId {
+        self.root_id
+    }
+
+    // 切换显示的根节点，不属于对模型的修改，不记录撤销
+    pub fn set_root(&mut self, id: ModelId) -> Result<(), ModelError> {
+        self.model(id)?;
+        self.root_id = id;
+        Ok(())
+    }
+
+    pub fn next_id(&self) -> ModelId {
+        self.next_id
+    }
+
+    pub fn unique_names(&self) -> bool {
+        self.unique_names
+    }
+
+    // 引用该模型的所有父节点，按ID排序
+    pub fn parents(&self, id: ModelId) -> Vec<ModelId> {
+        self.parents.parents(id)
+    }
+
+    // 所有没有父节点的模型，以及被设为入口的模型；没有父节点的排在前面
+    pub fn roots(&self) -> Vec<Node> {
+        let mut roots = self.models.values().filter(|model| model.ref_count == 0 || model.pinned).collect::<Vec<&Model>>();
+        roots.sort_by(|a, b| (a.ref_count > 0, &a.name, a.id).cmp(&(b.ref_count > 0, &b.name, b.id)));
+        roots.into_iter().map(Node::from).collect()
+    }
+
+    // 从所有顶层模型到该模型的路径，最多limit条
+    pub fn usage_paths(&self, id: ModelId, limit: usize) -> Result<UsagePaths, ModelError> {
+        self.model(id)?;
+        let (paths, truncated) = graph::usage_paths(id, &self.parents, limit);
+        let paths = paths.into_iter().map(|ids| {
+            let names = ids.iter().map(|id| model_label(*id, &self.models)).collect();
+            UsagePath { ids, names }
+        }).collect();
+        Ok(UsagePaths { paths, truncated })
+    }
+
+    pub fn search(&self, query: &str, mode: SearchMode, limit: usize) -> Result<SearchResults, ModelError> {
+        search::search_models(query, mode, self.root_id, &self.models, &self.parents, limit)
+    }
+
+    // 模型中的问题，包括读取模型文件时发现的问题
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.file_diagnostics.clone();
+        diagnostics.extend(lint::lint_models(&self.models, self.root_id, self.unique_names));
+        diagnostics
+    }
+
+    // 根据叶节点的测量值计算整棵树，未指定root_id时从当前根节点开始
+    pub fn evaluate(&self, leaf_values: &HashMap<ModelId, f64>, root_id: Option<ModelId>) -> Result<Evaluation, ModelError> {
+        evaluate::evaluate_tree(root_id.unwrap_or(self.root_id), &self.models, leaf_values)
+    }
+
+    // 用给定的子节点取值计算模型的算法，values以子节点名称为键
+    pub fn evaluate_algorithm(&self, id: ModelId, values: &HashMap<String, f64>) -> Result<f64, ModelError> {
+        let model = self.model(id)?;
+        let source = match (&model.children, &model.algorithm) {
+            (Some(_), Some(source)) => source,
+            _ => return Err(ModelError::not_a_composite(model)),
+        };
+        if algorithm::is_placeholder(source) {
+            return Err(ModelError::invalid_algorithm(model, AlgorithmError::new("算法尚未确定".to_string())));
+        }
+        let labels = children_labels(model, &self.models)?;
+        let expr = algorithm::compile(source, &labels).map_err(|e| ModelError::invalid_algorithm(model, e))?;
+        algorithm::evaluate(&expr, &|child| values.get(child).copied()).map_err(|e| ModelError::invalid_algorithm(model, e))
+    }
+
+    // 将从root_id出发、不超过depth层的模型导出为图，未指定root_id时从当前根节点开始
+    pub fn export_graph(&self, format: DiagramFormat, root_id: Option<ModelId>, depth: Option<usize>) -> Result<String, ModelError> {
+        diagram::export_graph(format, root_id.unwrap_or(self.root_id), depth, &self.models)
+    }
+
+    // 取出上次调用以来的修改，转为推送给前端的事件
+    pub fn take_events(&mut self) -> ChangeEvents {
+        events::take_events(self)
+    }
+
+    // 新加入的模型的默认名称，不与已有的名称重复
+    pub fn new_node_name(&self) -> String {
+        suggest_new_name_add(&self.models)
+    }
+
+    // 从父节点中删除模型时将被一并删除的模型，按从上到下的顺序；为空时只是从父节点中移除
+    pub fn preview_delete_node(&self, parent_id: ModelId, id: ModelId) -> Result<Vec<Node>, ModelError> {
+        child_position(parent_id, id, &self.models)?;
+        Ok(nodes(&graph::orphans_after_removal(id, self.root_id, &self.models), &self.models))
+    }
+
+    // 预览将组合模型改为叶节点时一并删除的模型，不修改模型；改为组合模型时为空
+    pub fn preview_toggle_has_children(&self, id: ModelId) -> Result<Vec<Node>, ModelError> {
+        self.model(id)?;
+        Ok(nodes(&self.orphans_of_children(id), &self.models))
+    }
+
+    // 计算批量重命名的结果，不修改模型
+    pub fn plan_batch_rename(&self, pattern: &str, replacement: &str) -> Result<BatchRenameReport, ModelError> {
+        plan_batch_rename(pattern, replacement, &self.models, self.unique_names)
+    }
+
+    // 重命名模型，要求名称唯一时与已有的模型重名则合并到该模型（无子节点时）或加后缀（有子节点时）
+    pub fn rename(&mut self, id: ModelId, new_name: &str) -> Result<UpdateNameResponse, ModelError> {
+        // 1. if the new node name is not duplicated, or names are not required to be unique, then simply apply
+          // 2. if the node name is duplicated, then check:
+          // if the node itself does not have children, then merge it into the existing node, update reference count, and: (reference count: needs to be updated)
+          //    if the nodes with the same name have children, then add all the children to the renamed node (updated)
+          //    if the nodes with the same name do not have children, do nothing (reference count updated)
+          // if the node has children, then rename the node to something else (different modified name, no update)
+        let model = self.models.get(&id).ok_or_else(|| ModelError::not_found(id))?;
+        if model.name == new_name{
+            return Ok(UpdateNameResponse{id, new_name: new_name.to_string()});
+        }
+        let name = model.name.clone();
+        let has_children = model.children.is_some();
+        // 撤销时需要恢复原模型、合并到的模型以及所有引用原模型的父节点
+        let mut affected_ids = self.parents.parents(id);
+        affected_ids.push(id);
+        // 不要求名称唯一时名称只用于显示，直接修改即可
+        let duplicate = if self.unique_names {
+            find_by_name(new_name, &self.models).into_iter().find(|other| *other != id)
+        } else {
+            None
+        };
+        let response = match duplicate{
+            // new name is duplicated with old names
+            Some(_) if has_children => {
+                let new_processed_name = suggest_new_name_dupe(new_name, &self.models);
+                // 虽然局部看起来不需要更新，但是可能有其他父节点有这个节点，所以需要更新
+                record_edit(self, format!("重命名{}为{}", name, new_processed_name), affected_ids, |graph| {
+                    update_dup_name_has_children_backend(id, &new_processed_name, graph)
+                })?;
+                UpdateNameResponse{id, new_name: new_processed_name}
+            }
+            Some(target_id) => {
+                // 后端搜索所有节点，将引用该模型的子节点改为引用同名的模型，更新reference count
+                affected_ids.push(target_id);
+                record_edit(self, format!("重命名{}为{}（合并）", name, new_name), affected_ids, |graph| {
+                    update_dup_name_no_children_backend(id, target_id, graph)
+                })?;
+                UpdateNameResponse{id: target_id, new_name: new_name.to_string()}
+            }
+            None => {
+                // 虽然局部看起来不需要更新，但是可能有其他父节点有这个节点，所以需要更新
+                record_edit(self, format!("重命名{}为{}", name, new_name), affected_ids, |graph| {
+                    update_non_dup_name_backend(id, new_name, graph)
+                })?;
+                UpdateNameResponse{id, new_name: new_name.to_string()}
+            }
+        };
+        // 当前显示的根节点被合并时随之切换
+        if self.root_id == id{
+            self.root_id = response.id;
+        }
+        Ok(response)
+    }
+
+    // 以正则表达式替换所有模型的名称，作为一次修改撤销；有名称冲突时不修改，只返回冲突
+    pub fn batch_rename(&mut self, pattern: &str, replacement: &str) -> Result<BatchRenameReport, ModelError>{
+        let mut report = plan_batch_rename(pattern, replacement, &self.models, self.unique_names)?;
+        if report.renames.is_empty() || !report.collisions.is_empty(){
+            return Ok(report);
+        }
+        let new_names = report.renames.iter().map(|rename| (rename.id, rename.new_name.clone())).collect::<HashMap<ModelId, String>>();
+        let mut parent_ids = new_names.keys().flat_map(|id| self.parents.parents(*id)).collect::<Vec<ModelId>>();
+        parent_ids.sort_unstable();
+        parent_ids.dedup();
+        let mut affected_ids = parent_ids.clone();
+        affected_ids.extend(new_names.keys().copied());
+        let description = format!("批量重命名{}个模型（{} → {}）", report.renames.len(), pattern, replacement);
+        record_edit(self, description, affected_ids, |graph| {
+            // 同一个父节点下的子节点可能互换名称，父节点的算法需要一次修改所有引用
+            for parent_id in &parent_ids{
+                let renamed = composite_children(*parent_id, &graph.models)?.into_iter()
+                    .filter_map(|child| Some((graph.models[&child].name.as_str(), new_names.get(&child)?.as_str())))
+                    .collect::<Vec<(&str, &str)>>();
+                let algorithm = graph.models[parent_id].algorithm.as_ref().and_then(|algorithm| algorithm::rename_references(algorithm, &renamed));
+                if let Some(algorithm) = algorithm{
+                    graph.models.get_mut(parent_id).ok_or_else(|| ModelError::not_found(*parent_id))?.algorithm = Some(algorithm);
+                }
+            }
+            for (id, new_name) in &new_names{
+                let model = graph.models.get_mut(id).ok_or_else(|| ModelError::not_found(*id))?;
+                model.name = new_name.clone();
+            }
+            Ok(())
+        })?;
+        report.applied = true;
+        Ok(report)
+    }
+
+    // 在父节点children的index处加入新的叶节点，index为None时加在最后
+    pub fn add_node(&mut self, parent_id: ModelId, index: Option<usize>, new_id: ModelId, new_name: &str) -> Result<(), ModelError>{
+        let description = format!("在{}中添加{}", model_label(parent_id, &self.models), new_name);
+        record_edit(self, description, vec![parent_id, new_id], |graph| {
+            add_node_to_parent(parent_id, index, new_id, new_name, &mut graph.models, &mut graph.parents)
+        })?;
+        // 撤销后再添加也不会重新使用这个ID
+        self.next_id = self.next_id.max(new_id + 1);
+        Ok(())
+    }
+
+    // 将id第一次出现的位置移到new_index，new_index是移动后的位置
+    pub fn move_child(&mut self, parent_id: ModelId, id: ModelId, new_index: usize) -> Result<(), ModelError>{
+        let description = format!("调整{}中{}的位置", model_label(parent_id, &self.models), model_label(id, &self.models));
+        record_edit(self, description, vec![parent_id], |graph| {
+            let position = child_position(parent_id, id, &graph.models)?;
+            let mut children = composite_children(parent_id, &graph.models)?;
+            check_child_index(parent_id, new_index, children.len(), &graph.models)?;
+            let child = children.remove(position);
+            children.insert(new_index, child);
+            graph.parents.set_children(parent_id, Some(children), &mut graph.models);
+            Ok(())
+        })
+    }
+
+    // 从父节点中删除模型的一次出现，删除后不再被引用的模型连同其下的模型一并删除
+    // 被设为入口的模型和当前的根节点即使不再被引用也保留
+    pub fn delete_node(&mut self, parent_id: ModelId, id: ModelId) -> Result<(), ModelError>{
+        child_position(parent_id, id, &self.models)?;
+        let orphans = graph::orphans_after_removal(id, self.root_id, &self.models);
+        let description = if orphans.len() > 1 {
+            format!("从{}中删除{}（连同其下{}个模型）", model_label(parent_id, &self.models), model_label(id, &self.models), orphans.len() - 1)
+        } else {
+            format!("从{}中删除{}", model_label(parent_id, &self.models), model_label(id, &self.models))
+        };
+        let mut affected_ids = vec![parent_id, id];
+        affected_ids.extend(orphans.iter().copied());
+        record_edit(self, description, affected_ids, |graph| {
+            remove_node_from_parent(parent_id, id, &orphans, &mut graph.models, &mut graph.parents)
+        })
+    }
+
+    // 将已有的模型加入另一个父节点，两个父节点共享同一个模型
+    pub fn link_existing_node(&mut self, parent_id: ModelId, id: ModelId) -> Result<(), ModelError>{
+        let description = format!("在{}中引用{}", model_label(parent_id, &self.models), model_label(id, &self.models));
+        // 会形成环时由record_edit拒绝
+        record_edit(self, description, vec![parent_id, id], |graph| {
+            let model = graph.models.get(&id).ok_or_else(|| ModelError::not_found(id))?;
+            let mut children = composite_children(parent_id, &graph.models)?;
+            if children.contains(&id){
+                return Err(ModelError::invariant_violation(model, format!("模型{}已是模型{}的子节点", model.name, model_label(parent_id, &graph.models))));
+            }
+            children.push(id);
+            graph.parents.set_children(parent_id, Some(children), &mut graph.models);
+            Ok(())
+        })
+    }
+
+    // 复制模型及其下的所有模型，加入new_parent，副本的ID从new_id开始依次分配
+    pub fn deep_copy_subtree(&mut self, id: ModelId, new_parent_id: ModelId, new_id: ModelId) -> Result<(), ModelError>{
+        // 子树中被多次引用的模型只复制一次，副本之间保持同样的共享关系
+        let mut order = graph::topological_order(id, &self.models)?;
+        order.reverse();
+        let copies = order.iter().enumerate().map(|(index, old_id)| (*old_id, new_id + index as ModelId)).collect::<HashMap<ModelId, ModelId>>();
+        if let Some(existing) = copies.values().find_map(|copy| self.models.get(copy)){
+            return Err(ModelError::invariant_violation(existing, format!("ID {}已被模型{}使用", existing.id, existing.name)));
+        }
+        let mut names = self.models.values().map(|model| model.name.clone()).collect::<HashSet<String>>();
+        let new_names = order.iter().map(|old_id| {
+            let new_name = suggest_copy_name(&self.models[old_id].name, &names);
+            names.insert(new_name.clone());
+            (*old_id, new_name)
+        }).collect::<HashMap<ModelId, String>>();
+        let description = format!("复制{}到{}", model_label(id, &self.models), model_label(new_parent_id, &self.models));
+        let mut affected_ids = copies.values().copied().collect::<Vec<ModelId>>();
+        affected_ids.push(new_parent_id);
+        record_edit(self, description, affected_ids, |graph| {
+            let mut children = composite_children(new_parent_id, &graph.models)?;
+            for old_id in &order{
+                let model = &graph.models[old_id];
+                // 算法以名称引用子节点，一次改为引用子节点的副本，各个改名互不影响
+                let renamed = model.children.iter().flatten()
+                    .map(|child| (graph.models[child].name.as_str(), new_names[child].as_str()))
+                    .collect::<Vec<(&str, &str)>>();
+                let algorithm = model.algorithm.as_ref()
+                    .map(|algorithm| algorithm::rename_references(algorithm, &renamed).unwrap_or_else(|| algorithm.clone()));
+                let copy = Model{
+                    id: copies[old_id],
+                    name: new_names[old_id].clone(),
+                    algorithm,
+                    children: model.children.as_ref().map(|children| children.iter().map(|child| copies[child]).collect()),
+                    ref_count: 0,
+                    pinned: false,
+                };
+                graph.parents.insert(copy, &mut graph.models);
+            }
+            children.push(copies[&id]);
+            graph.parents.set_children(new_parent_id, Some(children), &mut graph.models);
+            Ok(())
+        })?;
+        self.next_id = self.next_id.max(new_id + order.len() as ModelId);
+        Ok(())
+    }
+
+    // 将模型从一个父节点移到另一个父节点，只移动一次出现
+    pub fn move_node(&mut self, old_parent_id: ModelId, new_parent_id: ModelId, id: ModelId) -> Result<(), ModelError>{
+        let description = format!("将{}从{}移到{}", model_label(id, &self.models), model_label(old_parent_id, &self.models), model_label(new_parent_id, &self.models));
+        // 会形成环时由record_edit拒绝
+        record_edit(self, description, vec![old_parent_id, new_parent_id, id], |graph| {
+            let position = child_position(old_parent_id, id, &graph.models)?;
+            let mut old_children = composite_children(old_parent_id, &graph.models)?;
+            old_children.remove(position);
+            graph.parents.set_children(old_parent_id, Some(old_children), &mut graph.models);
+            // 新父节点没有子节点时出错，record_edit会恢复已移除的子节点
+            let mut new_children = composite_children(new_parent_id, &graph.models)?;
+            new_children.push(id);
+            graph.parents.set_children(new_parent_id, Some(new_children), &mut graph.models);
+            Ok(())
+        })
+    }
+
+    // 将源模型合并到目标模型，所有引用源模型的地方改为引用目标，作为一次修改撤销
+    pub fn merge_nodes(&mut self, source_id: ModelId, target_id: ModelId, strategy: MergeStrategy) -> Result<MergeReport, ModelError>{
+        let source = self.models.get(&source_id).ok_or_else(|| ModelError::not_found(source_id))?.clone();
+        let target = self.models.get(&target_id).ok_or_else(|| ModelError::not_found(target_id))?.clone();
+        if source_id == target_id{
+            return Err(ModelError::invariant_violation(&source, format!("不能将模型{}合并到自身", source.name)));
+        }
+        let (children, algorithm, discarded_algorithm) = merged_content(&source, &target, strategy);
+        // 源模型和目标原有的子节点各失去一次引用，合并后目标的子节点各得到一次引用
+        let mut removed_references = source.children.iter().flatten().chain(target.children.iter().flatten())
+            .filter(|child| **child != source_id && **child != target_id).copied().collect::<Vec<ModelId>>();
+        children.iter().flatten().for_each(|child| {
+            if let Some(position) = removed_references.iter().position(|id| id == child){
+                removed_references.remove(position);
+            }
+        });
+        let orphans = graph::orphans_after_removals(&removed_references, self.root_id, &self.models).into_iter()
+            .filter(|id| *id != source_id && *id != target_id).collect::<Vec<ModelId>>();
+        let relinked_parents = self.parents.parents(source_id).into_iter().filter(|parent| *parent != target_id).collect::<Vec<ModelId>>();
+        let dropped_children = nodes(target.children.iter().flatten().filter(|child| **child != source_id && !children.iter().flatten().any(|id| id == *child)), &self.models);
+        let removed = nodes(&orphans, &self.models);
+        let mut affected_ids = vec![source_id, target_id];
+        affected_ids.extend(relinked_parents.iter().chain(&orphans).copied());
+        let description = if orphans.is_empty() {
+            format!("将{}合并到{}", source.name, target.name)
+        } else {
+            format!("将{}合并到{}（删除了{}个不再被引用的模型）", source.name, target.name, orphans.len())
+        };
+        record_edit(self, description, affected_ids, |graph| {
+            for parent_id in &relinked_parents{
+                let old_children = composite_children(*parent_id, &graph.models)?;
+                // 父节点已引用目标时只去掉对源模型的引用
+                let new_children = if old_children.contains(&target_id) {
+                    old_children.into_iter().filter(|child| *child != source_id).collect()
+                } else {
+                    old_children.into_iter().map(|child| if child == source_id { target_id } else { child }).collect()
+                };
+                let parent = graph.models.get_mut(parent_id).ok_or_else(|| ModelError::not_found(*parent_id))?;
+                if let Some(algorithm) = parent.algorithm.as_ref().and_then(|algorithm| algorithm::rename_reference(algorithm, &source.name, &target.name)){
+                    parent.algorithm = Some(algorithm);
+                }
+                graph.parents.set_children(*parent_id, Some(new_children), &mut graph.models);
+            }
+            graph.parents.set_children(target_id, children.clone(), &mut graph.models);
+            let model = graph.models.get_mut(&target_id).ok_or_else(|| ModelError::not_found(target_id))?;
+            model.algorithm = algorithm.clone();
+            model.pinned |= source.pinned;
+            graph.parents.remove(source_id, &mut graph.models);
+            orphans.iter().for_each(|orphan| {
+                graph.parents.remove(*orphan, &mut graph.models);
+            });
+            // 并入的子节点可能与目标原有的子节点同名，算法无法区分
+            let model = &graph.models[&target_id];
+            if model.children.is_some(){
+                children_labels(model, &graph.models)?;
+            }
+            Ok(())
+        })?;
+        // 当前显示的根节点被合并时随之切换
+        if self.root_id == source_id{
+            self.root_id = target_id;
+        }
+        let added_children = nodes(children.iter().flatten().filter(|child| !target.children.iter().flatten().any(|id| id == *child)), &self.models);
+        Ok(MergeReport {
+            target: Node::from(&self.models[&target_id]),
+            source: Node::from(&source),
+            relinked_parents: nodes(&relinked_parents, &self.models),
+            added_children,
+            dropped_children,
+            removed,
+            algorithm,
+            discarded_algorithm,
+        })
+    }
+
+    // 设为入口的模型即使有父节点也出现在根节点列表中
+    pub fn set_pinned(&mut self, id: ModelId, pinned: bool) -> Result<(), ModelError>{
+        let name = model_label(id, &self.models);
+        let description = if pinned { format!("将{}设为入口", name) } else { format!("取消{}的入口", name) };
+        record_edit(self, description, vec![id], |graph| {
+            let model = graph.models.get_mut(&id).ok_or_else(|| ModelError::not_found(id))?;
+            model.pinned = pinned;
+            Ok(())
+        })
+    }
+
+    // 不改变任何模型，不记录撤销；已有重名的模型时不能要求名称唯一
+    pub fn set_unique_names(&mut self, unique_names: bool) -> Result<(), ModelError>{
+        if unique_names {
+            check_unique_names(&self.models)?;
+        }
+        self.modified |= self.unique_names != unique_names;
+        self.unique_names = unique_names;
+        Ok(())
+    }
+
+    // 转为以名称互相引用的模型，按名称排序，用于导出为扁平格式；有重名的模型时无法表示
+    pub fn file_models(&self) -> Result<Vec<FileModel>, ModelError> {
+        check_unique_names(&self.models)?;
+        let mut models = self.models.values().collect::<Vec<&Model>>();
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(models.into_iter().map(|model| FileModel {
+            name: model.name.clone(),
+            children: model.children.as_ref().map(|children| children.iter().map(|child| model_label(*child, &self.models)).collect()),
+            algorithm: model.algorithm.clone(),
+            pinned: model.pinned,
+        }).collect())
+    }
+
+    // 计算导入与当前模型的差异，不修改模型
+    pub fn plan_import(&self, file_models: Vec<FileModel>) -> Result<ImportReport, ModelError> {
+        let (_, report) = prepare_import(self, file_models)?;
+        Ok(report)
+    }
+
+    // 以导入的模型替换当前的模型，作为一次修改撤销：同名的模型保留原来的ID和入口设置，导入中没有的模型被删除
+    pub fn import(&mut self, file_models: Vec<FileModel>) -> Result<ImportReport, ModelError> {
+        let (models, mut report) = prepare_import(self, file_models)?;
+        if report.is_empty() {
+            return Ok(report);
+        }
+        let removed_ids = self.models.keys().filter(|id| !models.contains_key(id)).copied().collect::<Vec<ModelId>>();
+        let mut affected_ids = models.keys().copied().collect::<Vec<ModelId>>();
+        affected_ids.extend(removed_ids.iter().copied());
+        let next_id = models.keys().max().map_or(self.next_id, |id| self.next_id.max(id + 1));
+        let description = format!("导入模型（新增{}个，删除{}个，修改{}个）", report.added.len(), report.removed.len(), report.changed.len());
+        record_edit(self, description, affected_ids, |graph| {
+            models.into_values().for_each(|model| graph.parents.insert(model, &mut graph.models));
+            removed_ids.iter().for_each(|id| {
+                graph.parents.remove(*id, &mut graph.models);
+            });
+            Ok(())
+        })?;
+        self.next_id = next_id;
+        self.keep_root();
+        report.applied = true;
+        Ok(report)
+    }
+
+    // 去掉模型的全部子节点后不再被引用的子孙模型，根节点和固定的模型除外；叶节点返回空
+    fn orphans_of_children(&self, id: ModelId) -> Vec<ModelId> {
+        match self.models.get(&id).and_then(|model| model.children.as_ref()) {
+            Some(children) => graph::orphans_after_removals(children, self.root_id, &self.models),
+            None => vec![],
+        }
+    }
+
+    // 叶节点加上空的子节点列表和占位的算法，有子节点的模型则去掉子节点和算法
+    pub fn toggle_has_children(&mut self, id: ModelId) -> Result<(), ModelError>{
+        // 撤销时恢复原有的子节点和算法
+        // 改为叶节点时与delete_node相同，不再被引用的子孙模型一并删除
+        let orphans = self.orphans_of_children(id);
+        let description = if orphans.is_empty() {
+            format!("切换{}的子项", model_label(id, &self.models))
+        } else {
+            format!("切换{}的子项（删除其下{}个模型）", model_label(id, &self.models), orphans.len())
+        };
+        let mut affected_ids = vec![id];
+        affected_ids.extend(orphans.iter().copied());
+        record_edit(self, description, affected_ids, |graph| {
+            let model = graph.models.get_mut(&id).ok_or_else(|| ModelError::not_found(id))?;
+            // 删除子项时原有子节点的引用计数随之减少
+            let children = match model.children{
+                Some(_)=>{
+                    if model.algorithm.is_none(){
+                        return Err(ModelError::invariant_violation(model, format!("模型{}状态冲突：有子节点但无算法声明", model.name)));
+                    }
+                    model.algorithm = None;
+                    None
+                }
+                None=>{
+                    model.algorithm = Some("未定义算法".to_string());
+                    Some(vec![])
+                }
+            };
+            graph.parents.set_children(id, children, &mut graph.models);
+            orphans.iter().for_each(|orphan| {
+                graph.parents.remove(*orphan, &mut graph.models);
+            });
+            Ok(())
+        })
+    }
+
+    // 修改算法前检查算法能否解析、引用的子节点是否存在
+    pub fn update_algorithm(&mut self, id: ModelId, algorithm: &str) -> Result<(), ModelError>{
+        let description = format!("修改{}的算法", model_label(id, &self.models));
+        record_edit(self, description, vec![id], |graph| {
+            let model = graph.models.get(&id).ok_or_else(|| ModelError::not_found(id))?;
+            // 无子节点的模型不能有算法
+            if model.children.is_none(){
+                return Err(ModelError::not_a_composite(model));
+            }
+            let labels = children_labels(model, &graph.models)?;
+            algorithm::validate(algorithm, &labels).map_err(|e| ModelError::invalid_algorithm(model, e))?;
+            if let Some(model) = graph.models.get_mut(&id){
+                model.algorithm = Some(algorithm.to_string());
+            }
+            Ok(())
+        })
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    // 撤销最近一次修改，返回其描述
+    pub fn undo(&mut self) -> Option<String> {
+        let edit = self.history.undo(&mut self.models, &mut self.parents)?;
+        self.modified = true;
+        edit.record_changes(&mut self.changes, true);
+        let description = edit.description().to_string();
+        self.keep_root();
+        Some(description)
+    }
+
+    // 重做最近一次撤销的修改，返回其描述
+    pub fn redo(&mut self) -> Option<String> {
+        let edit = self.history.redo(&mut self.models, &mut self.parents)?;
+        self.modified = true;
+        edit.record_changes(&mut self.changes, false);
+        let description = edit.description().to_string();
+        self.keep_root();
+        Some(description)
+    }
+
+    // 根节点的切换不记录撤销，撤销添加或重做合并可能移除当前的根节点，此时改为第一个顶层模型
+    fn keep_root(&mut self) {
+        if self.models.contains_key(&self.root_id) {
+            return;
+        }
+        if let Some(root) = self.roots().first() {
+            self.root_id = root.id;
+        }
+    }
+
+    // 重放日志中的一条记录
+    pub fn apply_journal_entry(&mut self, entry: &JournalEntry) -> Result<(), ModelError>{
+        match entry {
+            JournalEntry::UpdateNodeName { id, new_name } => self.rename(*id, new_name).map(|_| ()),
+            JournalEntry::AddNode { parent_id, new_id, new_name } => self.add_node(*parent_id, None, *new_id, new_name),
+            JournalEntry::InsertNodeAt { parent_id, index, new_id, new_name } => self.add_node(*parent_id, Some(*index), *new_id, new_name),
+            JournalEntry::DeleteNode { parent_id, id } => self.delete_node(*parent_id, *id),
+            JournalEntry::ToggleHasChildren { id } => self.toggle_has_children(*id),
+            JournalEntry::UpdateAlgorithm { id, algorithm } => self.update_algorithm(*id, algorithm),
+            JournalEntry::AddRoot { id } => self.set_pinned(*id, true),
+            JournalEntry::RemoveRoot { id } => self.set_pinned(*id, false),
+            JournalEntry::SetUniqueNames { unique_names } => self.set_unique_names(*unique_names),
+            JournalEntry::LinkExistingNode { parent_id, id } => self.link_existing_node(*parent_id, *id),
+            JournalEntry::DeepCopySubtree { id, new_parent_id, new_id } => self.deep_copy_subtree(*id, *new_parent_id, *new_id),
+            JournalEntry::MoveNode { old_parent_id, new_parent_id, id } => self.move_node(*old_parent_id, *new_parent_id, *id),
+            JournalEntry::MoveChild { parent_id, id, new_index } => self.move_child(*parent_id, *id, *new_index),
+            JournalEntry::BatchRename { pattern, replacement } => self.batch_rename(pattern, replacement).map(|_| ()),
+            JournalEntry::MergeNodes { source_id, target_id, strategy } => self.merge_nodes(*source_id, *target_id, *strategy).map(|_| ()),
+            JournalEntry::ImportModels { models } => self.import(models.clone()).map(|_| ()),
+            // 撤销记录与崩溃前不同时不能静默跳过，否则恢复后的模型与崩溃前不一致
+            JournalEntry::Undo => self.undo().map(|_| ()).ok_or_else(|| ModelError::empty_history("撤销")),
+            JournalEntry::Redo => self.redo().map(|_| ()).ok_or_else(|| ModelError::empty_history("重做")),
+        }
+    }
+}
+
+fn update_dup_name_no_children_backend(id: ModelId, target_id: ModelId, graph: &mut ModelGraph) -> Result<(), ModelError>{
+    // this function is only called when the new name is duplicated, and the model does not have children
+    // the model should snap to the one that originally has this new name
+    // iterate through all the models and replace the children with the target
+    let model = graph.models.get(&id).ok_or_else(|| ModelError::not_found(id))?;
+    let (name, pinned) = (model.name.clone(), model.pinned);
+    let target = graph.models.get_mut(&target_id).ok_or_else(|| ModelError::not_found(target_id))?;
+    target.pinned |= pinned;
+    let target_name = target.name.clone();
+    // only the parents of the model need to be updated
+    for parent_id in graph.parents.parents(id){
+        let parent = match graph.models.get_mut(&parent_id){
+            Some(parent) => parent,
+            None => continue,
+        };
+        let children = parent.children.iter().flatten().map(|child| if *child == id { target_id } else { *child }).collect();
+        // 算法中对该子节点的引用也一并修改
+        if let Some(algorithm) = parent.algorithm.as_ref().and_then(|algorithm| algorithm::rename_reference(algorithm, &name, &target_name)){
+            parent.algorithm = Some(algorithm);
+        }
+        // the reference counts are updated along with the children
+        graph.parents.set_children(parent_id, Some(children), &mut graph.models);
+    }
+    graph.parents.remove(id, &mut graph.models);
+    Ok(())
+}
+
+fn suggest_new_name_dupe(new_name: &str, models: &HashMap<ModelId, Model>) -> String{
+    let mut new_name = new_name.to_string();
+    while !find_by_name(&new_name, models).is_empty(){
+        new_name = format!("{}（错误：重名）", new_name);
+    }
+    new_name
+}
+
+fn suggest_new_name_add(models: &HashMap<ModelId, Model>) -> String{
+    let mut new_name = "新节点".to_string();
+    let mut i = 0;
+    while !find_by_name(&new_name, models).is_empty(){
+        i += 1;
+        new_name = format!("新节点{}", i);
+    }
+    new_name
+}
+
+fn update_dup_name_has_children_backend(id: ModelId, new_processed_name: &str, graph: &mut ModelGraph) -> Result<(), ModelError>{
+    // this function is called when the new name is duplicated, and the model has children
+    // the model will not snap to any existing node because the new name is supposed to be different from any existing ...
+    replace_name(id, new_processed_name, graph)
+    // reference count should not change in this case
+}
+
+fn update_non_dup_name_backend(id: ModelId, new_name: &str, graph: &mut ModelGraph) -> Result<(), ModelError>{
+    // the logic should be the same as dup_name_has_children
+    update_dup_name_has_children_backend(id, new_name, graph)
+}
+
+fn replace_name(id: ModelId, new_name: &str, graph: &mut ModelGraph) -> Result<(), ModelError>{
+    let model = graph.models.get_mut(&id).ok_or_else(|| ModelError::not_found(id))?;
+    let old_name = std::mem::replace(&mut model.name, new_name.to_string());
+    // children以ID引用，不需要修改；父节点的算法以名称引用子节点，需要一并修改
+    for parent_id in graph.parents.parents(id){
+        if let Some(parent) = graph.models.get_mut(&parent_id){
+            if let Some(algorithm) = parent.algorithm.as_ref().and_then(|algorithm| algorithm::rename_reference(algorithm, &old_name, new_name)){
+                parent.algorithm = Some(algorithm);
+            }
+        }
+    }
+    Ok(())
+}
+
+// 计算以正则表达式pattern替换所有模型名称的结果，不修改模型；replacement中可以用$1等引用分组
+fn plan_batch_rename(pattern: &str, replacement: &str, models: &HashMap<ModelId, Model>, unique_names: bool) -> Result<BatchRenameReport, ModelError>{
+    let regex = regex::Regex::new(pattern).map_err(|e| ModelError::invalid_query(format!("正则表达式有误：{}", e)))?;
+    let mut renames = Vec::new();
+    for model in models.values(){
+        let new_name = regex.replace_all(&model.name, replacement);
+        if new_name == model.name{
+            continue;
+        }
+        if new_name.trim().is_empty(){
+            return Err(ModelError::invalid_query(format!("模型{}替换后的名称为空", model.name)));
+        }
+        renames.push(PlannedRename{id: model.id, old_name: model.name.clone(), new_name: new_name.into_owned()});
+    }
+    renames.sort_by(|a, b| (&a.old_name, a.id).cmp(&(&b.old_name, b.id)));
+    let mut collisions = Vec::new();
+    if unique_names{
+        // 重命名后的所有名称，未被重命名的模型保持原名称
+        let new_names = renames.iter().map(|rename| (rename.id, rename.new_name.as_str())).collect::<HashMap<ModelId, &str>>();
+        let mut groups: std::collections::BTreeMap<&str, Vec<ModelId>> = std::collections::BTreeMap::new();
+        models.values().for_each(|model| {
+            let name = new_names.get(&model.id).copied().unwrap_or(model.name.as_str());
+            groups.entry(name).or_default().push(model.id);
+        });
+        for (name, mut ids) in groups{
+            if ids.len() < 2 || !ids.iter().any(|id| new_names.contains_key(id)){
+                continue;
+            }
+            ids.sort_unstable();
+            let names = ids.iter().map(|id| models[id].name.clone()).collect();
+            collisions.push(NameCollision{name: name.to_string(), ids, names});
+        }
+    }
+    Ok(BatchRenameReport{renames, collisions, applied: false})
+}
+
+fn add_node_to_parent(parent_id: ModelId, index: Option<usize>, new_id: ModelId, new_name: &str, models: &mut HashMap<ModelId, Model>, parents: &mut ParentIndex) -> Result<(), ModelError>{
+    // new id should not exist in models
+    if let Some(existing) = models.get(&new_id){
+        return Err(ModelError::invariant_violation(existing, format!("ID {}已被模型{}使用", new_id, existing.name)));
+    }
+    let mut children = composite_children(parent_id, models)?;
+    let index = index.unwrap_or(children.len());
+    check_child_index(parent_id, index, children.len() + 1, models)?;
+    children.insert(index, new_id);
+    // add new model with no children or algorithm
+    parents.insert(Model{id: new_id, name: new_name.to_string(), algorithm: None, children: None, ref_count: 0, pinned: false}, models);
+    // reference counts are updated along with the children
+    parents.set_children(parent_id, Some(children), models);
+    Ok(())
+}
+
+// children的长度为len时，index是否是有效的位置
+fn check_child_index(parent_id: ModelId, index: usize, len: usize, models: &HashMap<ModelId, Model>) -> Result<(), ModelError>{
+    if index < len{
+        return Ok(());
+    }
+    let parent = models.get(&parent_id).ok_or_else(|| ModelError::not_found(parent_id))?;
+    Err(ModelError::invariant_violation(parent, format!("位置{}超出了模型{}的子节点范围（共{}个位置）", index, parent.name, len)))
+}
+
+// id在父节点children中第一次出现的位置
+fn child_position(parent_id: ModelId, id: ModelId, models: &HashMap<ModelId, Model>) -> Result<usize, ModelError>{
+    let model = models.get(&id).ok_or_else(|| ModelError::not_found(id))?;
+    let children = composite_children(parent_id, models)?;
+    children.iter().position(|child| *child == id).ok_or_else(|| {
+        ModelError::invariant_violation(model, format!("模型{}不是模型{}的子节点", model.name, model_label(parent_id, models)))
+    })
+}
+
+// orphans为graph::orphans_after_removal的结果，即删除后不再被引用的模型
+fn remove_node_from_parent(parent_id: ModelId, id: ModelId, orphans: &[ModelId], models: &mut HashMap<ModelId, Model>, parents: &mut ParentIndex) -> Result<(), ModelError>{
+    // remove only one occurrence of the node from the parent, the same child may appear more than once
+    let position = child_position(parent_id, id, models)?;
+    let mut children = composite_children(parent_id, models)?;
+    children.remove(position);
+    parents.set_children(parent_id, Some(children), models);
+    // remove the node and its descendants that are no longer referenced by any model
+    orphans.iter().for_each(|orphan| {
+        parents.remove(*orphan, models);
+    });
+    Ok(())
+}
+
+// 副本的名称加后缀，不与已有的名称重复
+fn suggest_copy_name(name: &str, names: &HashSet<String>) -> String{
+    let mut new_name = format!("{}（副本）", name);
+    let mut i = 1;
+    while names.contains(&new_name){
+        i += 1;
+        new_name = format!("{}（副本{}）", name, i);
+    }
+    new_name
+}
+
+// 合并后目标的子节点和算法，以及未被采用的算法
+fn merged_content(source: &Model, target: &Model, strategy: MergeStrategy) -> (Option<Vec<ModelId>>, Option<String>, Option<String>){
+    let (children, algorithm, discarded) = match strategy {
+        MergeStrategy::Replace => (source.children.clone(), source.algorithm.clone(), target.algorithm.clone()),
+        MergeStrategy::Union => match (&target.children, &source.children) {
+            (Some(target_children), Some(source_children)) => {
+                let mut children = target_children.clone();
+                source_children.iter().for_each(|child| {
+                    if !children.contains(child){
+                        children.push(*child);
+                    }
+                });
+                let (algorithm, discarded) = match (&target.algorithm, &source.algorithm) {
+                    (Some(target_algorithm), Some(source_algorithm)) if algorithm::is_placeholder(target_algorithm) => (Some(source_algorithm.clone()), Some(target_algorithm.clone())),
+                    (target_algorithm, source_algorithm) => (target_algorithm.clone(), source_algorithm.clone()),
+                };
+                (Some(children), algorithm, discarded)
+            }
+            (None, Some(_)) => (source.children.clone(), source.algorithm.clone(), None),
+            _ => (target.children.clone(), target.algorithm.clone(), None),
+        },
+    };
+    // 目标是源模型的父节点或子节点时，合并后不能引用源模型或自身
+    let children = children.map(|children| children.into_iter().filter(|child| *child != source.id && *child != target.id).collect());
+    let discarded = discarded.filter(|discarded| !algorithm::is_placeholder(discarded) && Some(discarded) != algorithm.as_ref());
+    (children, algorithm, discarded)
+}
+
+fn nodes<'a>(ids: impl IntoIterator<Item = &'a ModelId>, models: &HashMap<ModelId, Model>) -> Vec<Node>{
+    ids.into_iter().filter_map(|id| models.get(id)).map(Node::from).collect()
+}
+
+// 按ID顺序找出第一个与之前的模型重名的模型
+fn check_unique_names(models: &HashMap<ModelId, Model>) -> Result<(), ModelError> {
+    let mut sorted = models.values().collect::<Vec<&Model>>();
+    sorted.sort_by_key(|model| model.id);
+    let mut seen = HashSet::new();
+    match sorted.into_iter().find(|model| !seen.insert(model.name.as_str())) {
+        Some(duplicate) => Err(ModelError::name_conflict(duplicate)),
+        None => Ok(()),
+    }
+}
+
+// 将导入的模型按名称对应到当前的模型，新的模型从next_id起按名称顺序分配ID
+// 返回导入后的所有模型（引用计数由ParentIndex在插入时计算）及差异
+fn prepare_import(graph: &ModelGraph, file_models: Vec<FileModel>) -> Result<(HashMap<ModelId, Model>, ImportReport), ModelError> {
+    check_unique_names(&graph.models)?;
+    // 与读取版本1的文件相同：补全叶节点并检查环
+    let imported = build_models(file_models).map_err(|e| ModelError::invalid_import(None, e.to_string()))?.models;
+    // 表格和大纲在解析时已按行检查，这里拦下其他来源中有子节点却没有算法（或反之）的模型
+    let mut mismatched = imported.values().filter(|model| model.children.is_some() != model.algorithm.is_some())
+        .map(|model| model.name.clone()).collect::<Vec<String>>();
+    if !mismatched.is_empty() {
+        mismatched.sort();
+        return Err(ModelError::invalid_import(None, format!("以下模型的子节点与算法不一致：{}", mismatched.join("、"))));
+    }
+    let existing = graph.models.values().map(|model| (model.name.as_str(), model)).collect::<HashMap<&str, &Model>>();
+    let mut imported = imported.into_values().collect::<Vec<Model>>();
+    imported.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut next_id = graph.next_id;
+    let ids = imported.iter().map(|model| {
+        let id = existing.get(model.name.as_str()).map_or_else(|| {
+            next_id += 1;
+            next_id - 1
+        }, |existing| existing.id);
+        (model.id, id)
+    }).collect::<HashMap<ModelId, ModelId>>();
+    let names = imported.iter().map(|model| (model.id, model.name.clone())).collect::<HashMap<ModelId, String>>();
+    let child_names = |children: &Option<Vec<ModelId>>, models: &HashMap<ModelId, Model>| {
+        children.as_ref().map(|children| children.iter().map(|child| model_label(*child, models)).collect::<Vec<String>>())
+    };
+    let mut report = ImportReport { added: vec![], removed: vec![], changed: vec![], applied: false };
+    let mut models = HashMap::new();
+    for model in imported {
+        let id = ids[&model.id];
+        let new_children = model.children.as_ref().map(|children| children.iter().map(|child| names[child].clone()).collect::<Vec<String>>());
+        match existing.get(model.name.as_str()) {
+            Some(old) => {
+                let old_children = child_names(&old.children, &graph.models);
+                if old_children != new_children || old.algorithm != model.algorithm {
+                    report.changed.push(ModelDiff {
+                        name: model.name.clone(),
+                        old_children,
+                        new_children,
+                        old_algorithm: old.algorithm.clone(),
+                        new_algorithm: model.algorithm.clone(),
+                    });
+                }
+            }
+            None => report.added.push(model.name.clone()),
+        }
+        let pinned = existing.get(model.name.as_str()).map_or(model.pinned, |old| old.pinned || model.pinned);
+        let children = model.children.map(|children| children.iter().map(|child| ids[child]).collect());
+        models.insert(id, Model { id, name: model.name, algorithm: model.algorithm, children, ref_count: 0, pinned });
+    }
+    report.removed = graph.models.values().filter(|model| !models.contains_key(&model.id)).map(|model| model.name.clone()).collect();
+    report.removed.sort();
+    Ok((models, report))
+}
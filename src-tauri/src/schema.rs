@@ -0,0 +1,79 @@
+// 模型文件格式
+// 版本1：模型数组，模型之间以名称互相引用，叶节点不单独保存；旧版模型目录中的每个文件是其中的一个模型
+// 版本2：带版本号的对象，每个模型有固定的ID，children引用ID，名称只用于显示，叶节点也单独保存
+// 读取版本1的文件时按名称排序分配ID，保存时总是写入当前版本
+use crate::error::ModelError;
+use crate::ModelId;
+use anyhow::{Context, Result};
+
+pub const CURRENT_VERSION: u32 = 2;
+
+// 字段按字母序排列，保存时输出的键顺序与现有模型文件一致
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct FileModel {
+    pub algorithm: Option<String>,
+    pub children: Option<Vec<String>>,
+    pub name: String,
+    // 只在为true时写入，不影响没有入口的模型文件
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub pinned: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct FileModelV2 {
+    pub algorithm: Option<String>,
+    pub children: Option<Vec<ModelId>>,
+    pub id: ModelId,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub pinned: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ModelFile {
+    pub models: Vec<FileModelV2>,
+    // 下一个新模型的ID，删除的模型的ID不会被重新使用
+    pub next_id: ModelId,
+    // 为true时模型名称不能重复，重命名为已有名称时按旧版的规则合并或加后缀
+    pub unique_names: bool,
+    pub version: u32,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+pub enum AnyModelFile {
+    V1(Vec<FileModel>),
+    V2(ModelFile),
+}
+
+pub fn parse(content: &str) -> Result<AnyModelFile, ModelError> {
+    let value = serde_json::from_str::<serde_json::Value>(content).map_err(parse_error)?;
+    if value.is_array() {
+        let file_models = serde_json::from_value(value).map_err(parse_error)?;
+        return Ok(AnyModelFile::V1(file_models));
+    }
+    let version = value.get("version").and_then(|version| version.as_u64())
+        .ok_or_else(|| ModelError::invalid_import(None, "模型文件中未找到版本号".to_string()))?;
+    if version != CURRENT_VERSION as u64 {
+        return Err(ModelError::invalid_import(None, format!("不支持版本为{}的模型文件，当前版本为{}", version, CURRENT_VERSION)));
+    }
+    let model_file = serde_json::from_value(value).map_err(parse_error)?;
+    Ok(AnyModelFile::V2(model_file))
+}
+
+// 从字符串解析时有行号，从Value转换时行号为0
+fn parse_error(error: serde_json::Error) -> ModelError {
+    let line = (error.line() > 0).then(|| error.line());
+    ModelError::invalid_import(line, format!("解析模型文件错误：{}", error))
+}
+
+pub fn to_bytes(model_file: &ModelFile) -> Result<Vec<u8>> {
+    // 缩进4个空格，与现有模型文件格式保持一致
+    let mut content = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut content, formatter);
+    serde::Serialize::serialize(model_file, &mut serializer).context("序列化模型错误")?;
+    Ok(content)
+}
@@ -0,0 +1,341 @@
+// 模型搜索：按名称子串、全拼、拼音首字母或正则表达式查找模型，也查找算法中的引用，结果按匹配程度排序
+use crate::error::ModelError;
+use crate::graph::{shortest_path_parents, usage_paths};
+use crate::index::ParentIndex;
+use crate::{model_label, Model, ModelId, UsagePath};
+use pinyin::ToPinyinMulti;
+use regex::Regex;
+use std::collections::HashMap;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SearchMode {
+    // 名称子串、全拼或首字母，以及算法中的子串
+    Fuzzy,
+    // 名称或算法中的子串，不区分大小写
+    Substring,
+    // 名称或算法匹配正则表达式
+    Regex,
+}
+
+// 匹配方式，排序时按声明顺序优先
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum MatchKind {
+    Exact,
+    Prefix,
+    Substring,
+    // 每个字都按全拼匹配，最后一个字可以只写拼音的开头
+    Pinyin,
+    // 至少有一个字只按首字母匹配，例如"gnztmk"或"gongnengzt"
+    Initials,
+    // 只有算法匹配，即算法中引用了名称匹配的子节点
+    Algorithm,
+}
+
+#[derive(serde::Serialize, Clone)]
+pub struct SearchHit {
+    pub id: ModelId,
+    pub name: String,
+    pub has_children: bool,
+    pub kind: MatchKind,
+    // 越大越靠前
+    pub score: u32,
+    // 从根节点到该模型的一条最短路径；从根节点无法到达时为从某个顶层模型出发的路径
+    pub path: UsagePath,
+    pub reachable: bool,
+}
+
+#[derive(serde::Serialize, Clone)]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+    // 结果数超过limit时只返回排在前面的部分
+    pub truncated: bool,
+}
+
+enum Matcher {
+    // 小写的查询，以及去掉空格和隔音符号后用于拼音匹配的查询，查询不全是字母和数字时为None
+    Text { query: String, pinyin: Option<String> },
+    Regex(Regex),
+}
+
+pub fn search_models(query: &str, mode: SearchMode, root_id: ModelId, models: &HashMap<ModelId, Model>, parents: &ParentIndex, limit: usize) -> Result<SearchResults, ModelError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(SearchResults { hits: vec![], truncated: false });
+    }
+    let matcher = match mode {
+        SearchMode::Regex => Matcher::Regex(Regex::new(query)
+            .map_err(|e| ModelError::invalid_query(format!("正则表达式有误：{}", e)))?),
+        SearchMode::Fuzzy | SearchMode::Substring => {
+            let query = query.to_lowercase();
+            let compact = query.chars().filter(|c| !c.is_whitespace() && *c != '\'').collect::<String>();
+            let pinyin = if mode == SearchMode::Fuzzy && compact.chars().all(|c| c.is_ascii_alphanumeric()) { Some(compact) } else { None };
+            Matcher::Text { query, pinyin }
+        }
+    };
+    let mut matches = models.values().filter_map(|model| {
+        let (kind, score) = match_model(model, &matcher)?;
+        Some((model, kind, score))
+    }).collect::<Vec<(&Model, MatchKind, u32)>>();
+    // 同等匹配时名称短的更接近查询
+    matches.sort_by(|(a, _, a_score), (b, _, b_score)| {
+        b_score.cmp(a_score).then_with(|| (a.name.chars().count(), &a.name, a.id).cmp(&(b.name.chars().count(), &b.name, b.id)))
+    });
+    let truncated = matches.len() > limit;
+    matches.truncate(limit);
+    let previous = shortest_path_parents(root_id, models);
+    let hits = matches.into_iter().map(|(model, kind, score)| {
+        let (ids, reachable) = path_to(model.id, root_id, &previous, parents);
+        let names = ids.iter().map(|id| model_label(*id, models)).collect();
+        SearchHit {
+            id: model.id,
+            name: model.name.clone(),
+            has_children: model.children.is_some(),
+            kind,
+            score,
+            path: UsagePath { ids, names },
+            reachable,
+        }
+    }).collect();
+    Ok(SearchResults { hits, truncated })
+}
+
+fn match_model(model: &Model, matcher: &Matcher) -> Option<(MatchKind, u32)> {
+    match matcher {
+        Matcher::Regex(regex) => {
+            if let Some(found) = regex.find(&model.name) {
+                let kind = match (found.start(), found.end()) {
+                    (0, end) if end == model.name.len() => MatchKind::Exact,
+                    (0, _) => MatchKind::Prefix,
+                    _ => MatchKind::Substring,
+                };
+                return Some((kind, kind_score(kind)));
+            }
+            model.algorithm.as_deref().filter(|source| regex.is_match(source))?;
+            Some((MatchKind::Algorithm, kind_score(MatchKind::Algorithm)))
+        }
+        Matcher::Text { query, pinyin } => {
+            let name = model.name.to_lowercase();
+            if name == *query {
+                return Some((MatchKind::Exact, kind_score(MatchKind::Exact)));
+            }
+            if name.starts_with(query.as_str()) {
+                return Some((MatchKind::Prefix, kind_score(MatchKind::Prefix)));
+            }
+            if name.contains(query.as_str()) {
+                return Some((MatchKind::Substring, kind_score(MatchKind::Substring)));
+            }
+            if let Some(found) = pinyin.as_deref().and_then(|pinyin| match_pinyin(&model.name, pinyin)) {
+                let kind = if found.full { MatchKind::Pinyin } else { MatchKind::Initials };
+                // 从第一个字开始、只用了常用读音的排在前面
+                let bonus = if found.from_start { 5 } else { 0 } + if found.primary { 2 } else { 0 };
+                return Some((kind, kind_score(kind) + bonus));
+            }
+            let source = model.algorithm.as_deref()?;
+            if source.to_lowercase().contains(query.as_str()) {
+                return Some((MatchKind::Algorithm, kind_score(MatchKind::Algorithm)));
+            }
+            None
+        }
+    }
+}
+
+fn kind_score(kind: MatchKind) -> u32 {
+    match kind {
+        MatchKind::Exact => 100,
+        MatchKind::Prefix => 80,
+        MatchKind::Substring => 60,
+        MatchKind::Pinyin => 40,
+        MatchKind::Initials => 20,
+        MatchKind::Algorithm => 10,
+    }
+}
+
+// 名称中每个字的读音：多音字的常用读音在前，非汉字取其小写形式；ü按输入法的习惯写作v
+fn readings(name: &str) -> Vec<Vec<String>> {
+    name.chars().map(|c| match c.to_pinyin_multi() {
+        Some(multi) => {
+            let mut readings: Vec<String> = Vec::new();
+            multi.into_iter().map(|pinyin| pinyin.plain().replace('ü', "v")).for_each(|reading| {
+                // 只是声调不同的读音去掉声调后相同
+                if !readings.contains(&reading) {
+                    readings.push(reading);
+                }
+            });
+            readings
+        }
+        None => vec![c.to_lowercase().collect()],
+    }).collect()
+}
+
+// 按拼音匹配的程度，比较时依次看是否全拼、是否只用了常用读音、是否从第一个字开始
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct PinyinMatch {
+    full: bool,
+    primary: bool,
+    from_start: bool,
+}
+
+// 按拼音匹配名称中连续的若干个字，有多种匹配方式时取最好的
+fn match_pinyin(name: &str, query: &str) -> Option<PinyinMatch> {
+    let readings = readings(name);
+    let mut memo = HashMap::new();
+    (0..readings.len()).filter_map(|start| {
+        let (full, primary) = match_pinyin_at(&readings, start, query, &mut memo)?;
+        Some(PinyinMatch { full, primary, from_start: start == 0 })
+    }).max()
+}
+
+// 从第index个字开始匹配query，返回(是否每个字都按全拼匹配, 是否只用了常用读音)
+// memo以(index, 剩余查询的长度)记录已知的结果
+fn match_pinyin_at(readings: &[Vec<String>], index: usize, query: &str, memo: &mut HashMap<(usize, usize), Option<(bool, bool)>>) -> Option<(bool, bool)> {
+    if query.is_empty() {
+        return Some((true, true));
+    }
+    if index == readings.len() {
+        return None;
+    }
+    if let Some(result) = memo.get(&(index, query.len())) {
+        return *result;
+    }
+    let mut result = None;
+    for (i, reading) in readings[index].iter().enumerate() {
+        let primary = i == 0;
+        let mut candidates = Vec::new();
+        if reading.starts_with(query) {
+            // 最后一个字只写了拼音的开头
+            candidates.push((true, true));
+        } else if let Some(rest) = query.strip_prefix(reading.as_str()) {
+            candidates.extend(match_pinyin_at(readings, index + 1, rest, memo));
+        }
+        let initial = reading.chars().next().filter(|c| c.is_ascii_alphabetic() && reading.len() > 1);
+        if let Some(rest) = initial.and_then(|initial| query.strip_prefix(initial)) {
+            candidates.extend(match_pinyin_at(readings, index + 1, rest, memo).map(|(_, rest_primary)| (false, rest_primary)));
+        }
+        result = candidates.into_iter().map(|(full, rest_primary)| (full, primary && rest_primary)).chain(result).max();
+    }
+    memo.insert((index, query.len()), result);
+    result
+}
+
+// 从根节点到id的最短路径及id是否能从根节点到达，不能到达时改用从某个顶层模型出发的路径
+fn path_to(id: ModelId, root_id: ModelId, previous: &HashMap<ModelId, ModelId>, parents: &ParentIndex) -> (Vec<ModelId>, bool) {
+    if id == root_id || previous.contains_key(&id) {
+        let mut path = vec![id];
+        let mut current = id;
+        while let Some(parent) = previous.get(&current) {
+            path.push(*parent);
+            current = *parent;
+        }
+        path.reverse();
+        return (path, true);
+    }
+    let (mut paths, _) = usage_paths(id, parents, 1);
+    (paths.pop().unwrap_or_else(|| vec![id]), false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::FileModel;
+    use crate::{build_models, find_by_name};
+
+    // 功能状态模块 → [心功能, 肺功能, 功能, 重度]，另有不可到达的功能状态评分
+    fn sample() -> (HashMap<ModelId, Model>, ParentIndex, ModelId) {
+        let children = ["心功能", "肺功能", "功能", "重度"].map(String::from).to_vec();
+        let loaded = build_models(vec![
+            FileModel { name: "功能状态模块".to_string(), children: Some(children), algorithm: Some("mean(心功能, 肺功能, 功能, 重度)".to_string()), pinned: false },
+            FileModel { name: "功能状态评分".to_string(), children: None, algorithm: None, pinned: false },
+        ]).unwrap();
+        let root_id = find_by_name("功能状态模块", &loaded.models)[0];
+        (loaded.models, loaded.parents, root_id)
+    }
+
+    fn search(query: &str, mode: SearchMode, limit: usize) -> Result<SearchResults, ModelError> {
+        let (models, parents, root_id) = sample();
+        search_models(query, mode, root_id, &models, &parents, limit)
+    }
+
+    // 结果的名称和匹配方式
+    fn hits(query: &str) -> Vec<(String, MatchKind)> {
+        search(query, SearchMode::Fuzzy, 100).unwrap().hits.into_iter().map(|hit| (hit.name, hit.kind)).collect()
+    }
+
+    fn hit(name: &str, kind: MatchKind) -> (String, MatchKind) {
+        (name.to_string(), kind)
+    }
+
+    #[test]
+    fn full_pinyin() {
+        assert_eq!(hits("xingongneng"), [hit("心功能", MatchKind::Pinyin)]);
+        // 最后一个字只写拼音的开头，空格和隔音符号被忽略
+        assert_eq!(hits("xin gong'n"), [hit("心功能", MatchKind::Pinyin)]);
+    }
+
+    #[test]
+    fn initials() {
+        assert_eq!(hits("xgn"), [hit("心功能", MatchKind::Initials)]);
+        assert_eq!(hits("gnztmk"), [hit("功能状态模块", MatchKind::Initials)]);
+        // 全拼与首字母混用
+        assert_eq!(hits("gongnengzt"), [hit("功能状态模块", MatchKind::Initials), hit("功能状态评分", MatchKind::Initials)]);
+    }
+
+    #[test]
+    fn heteronym_prefers_common_reading() {
+        let zhong = search("zhongdu", SearchMode::Fuzzy, 100).unwrap().hits;
+        let chong = search("chongdu", SearchMode::Fuzzy, 100).unwrap().hits;
+        assert_eq!((zhong[0].name.as_str(), chong[0].name.as_str()), ("重度", "重度"));
+        assert!(zhong[0].score > chong[0].score);
+    }
+
+    #[test]
+    fn exact_match_ranks_above_fuzzy_matches() {
+        assert_eq!(hits("功能"), [
+            hit("功能", MatchKind::Exact),
+            hit("功能状态模块", MatchKind::Prefix),
+            hit("功能状态评分", MatchKind::Prefix),
+            hit("心功能", MatchKind::Substring),
+            hit("肺功能", MatchKind::Substring),
+        ]);
+        // 从第一个字开始的拼音匹配排在前面
+        assert_eq!(hits("gongneng")[..3], [
+            hit("功能", MatchKind::Pinyin),
+            hit("功能状态模块", MatchKind::Pinyin),
+            hit("功能状态评分", MatchKind::Pinyin),
+        ]);
+    }
+
+    #[test]
+    fn algorithm_references_rank_last() {
+        assert_eq!(hits("心功能"), [hit("心功能", MatchKind::Exact), hit("功能状态模块", MatchKind::Algorithm)]);
+        // Substring模式不按拼音匹配
+        let results = search("xgn", SearchMode::Substring, 100).unwrap();
+        assert!(results.hits.is_empty());
+    }
+
+    #[test]
+    fn results_beyond_limit_are_truncated() {
+        let results = search("功能", SearchMode::Fuzzy, 2).unwrap();
+        assert!(results.truncated);
+        assert_eq!(results.hits.iter().map(|hit| hit.name.as_str()).collect::<Vec<&str>>(), ["功能", "功能状态模块"]);
+        assert!(!search("功能", SearchMode::Fuzzy, 5).unwrap().truncated);
+    }
+
+    #[test]
+    fn hits_carry_path_from_root() {
+        let results = search("肺功能", SearchMode::Fuzzy, 100).unwrap();
+        assert_eq!(results.hits[0].path.names, ["功能状态模块", "肺功能"]);
+        assert!(results.hits[0].reachable);
+        let results = search("评分", SearchMode::Fuzzy, 100).unwrap();
+        assert_eq!(results.hits[0].path.names, ["功能状态评分"]);
+        assert!(!results.hits[0].reachable);
+    }
+
+    #[test]
+    fn regex_mode() {
+        let results = search("^.功能$", SearchMode::Regex, 100).unwrap();
+        assert_eq!(results.hits.iter().map(|hit| hit.name.as_str()).collect::<Vec<&str>>(), ["心功能", "肺功能"]);
+        assert!(results.hits.iter().all(|hit| hit.kind == MatchKind::Exact));
+        let error = search("功能(", SearchMode::Regex, 100).err().unwrap();
+        assert!(matches!(&error, ModelError::InvalidQuery { message } if message.starts_with("正则表达式有误")), "{:?}", error);
+    }
+}
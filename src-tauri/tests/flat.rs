@@ -0,0 +1,102 @@
+// 边表CSV和大纲的解析与输出
+use app::error::ModelError;
+use app::flat::{self, FlatFormat};
+use app::schema::FileModel;
+
+fn composite(name: &str, children: &[&str], algorithm: &str) -> FileModel {
+    FileModel {
+        name: name.to_string(),
+        children: Some(children.iter().map(|child| child.to_string()).collect()),
+        algorithm: Some(algorithm.to_string()),
+        pinned: false,
+    }
+}
+
+fn leaf(name: &str) -> FileModel {
+    FileModel { name: name.to_string(), children: None, algorithm: None, pinned: false }
+}
+
+// A → [B, C]，B和C共享D，C另有没有子节点的组合模型E，F是不被引用的叶节点
+fn sample() -> Vec<FileModel> {
+    vec![
+        composite("A", &["B", "C"], "B + C"),
+        composite("B", &["D"], "D"),
+        composite("C", &["D", "E"], "D * E"),
+        leaf("D"),
+        composite("E", &[], "未定义算法"),
+        leaf("F"),
+    ]
+}
+
+// 只比较会写入文件的内容，叶节点在导入后由build_models补全，不一定出现在结果中
+fn summary(file_models: &[FileModel]) -> Vec<(String, Option<Vec<String>>, Option<String>)> {
+    let mut summary = file_models.iter().filter(|model| model.children.is_some() || model.algorithm.is_some() || model.name == "F")
+        .map(|model| (model.name.clone(), model.children.clone(), model.algorithm.clone())).collect::<Vec<_>>();
+    summary.sort();
+    summary
+}
+
+#[test]
+fn csv_round_trip() {
+    let content = flat::render(FlatFormat::Csv, &sample()).unwrap();
+    assert!(content.starts_with("\u{feff}parent,child,position,parent_algorithm\n"));
+    assert!(content.contains("C,D,1,D * E\nC,E,2,D * E\n"));
+    assert!(content.contains("E,,,未定义算法\n"));
+    assert!(content.contains("F,,,\n"));
+    assert!(!content.contains("\nD,"));
+    let parsed = flat::parse(FlatFormat::Csv, &content).unwrap();
+    assert_eq!(summary(&parsed), summary(&sample()));
+}
+
+#[test]
+fn outline_round_trip_expands_shared_models_once() {
+    let content = flat::render(FlatFormat::Outline, &sample()).unwrap();
+    assert_eq!(content, "A = B + C\n  B = D\n    D\n  C = D * E\n    D\n    E = 未定义算法\nF\n");
+    let parsed = flat::parse(FlatFormat::Outline, &content).unwrap();
+    assert_eq!(summary(&parsed), summary(&sample()));
+}
+
+#[test]
+fn csv_orders_children_by_position() {
+    let content = "parent,child,position,parent_algorithm\r\nA,C,2,B + C\r\nA,B,1,\r\n,,,\r\nA,D,,\r\n";
+    let parsed = flat::parse(FlatFormat::Csv, content).unwrap();
+    assert_eq!(summary(&parsed), vec![("A".to_string(), Some(vec!["B".to_string(), "C".to_string(), "D".to_string()]), Some("B + C".to_string()))]);
+}
+
+#[test]
+fn csv_errors_report_line() {
+    let conflicting = "parent,child,position,parent_algorithm\nA,B,1,B\nA,C,2,C\n";
+    assert!(matches!(flat::parse(FlatFormat::Csv, conflicting), Err(ModelError::InvalidImport { line: Some(3), .. })));
+    let duplicate = "parent,child,position\nA,B,1\nA,C,1\n";
+    assert!(matches!(flat::parse(FlatFormat::Csv, duplicate), Err(ModelError::InvalidImport { line: Some(3), .. })));
+    let missing_header = "from,to\nA,B\n";
+    assert!(matches!(flat::parse(FlatFormat::Csv, missing_header), Err(ModelError::InvalidImport { line: Some(1), .. })));
+}
+
+#[test]
+fn outline_errors_report_line() {
+    let indent = "A = B\n    B = C\n  C\n";
+    assert!(matches!(flat::parse(FlatFormat::Outline, indent), Err(ModelError::InvalidImport { line: Some(3), .. })));
+    let redefined = "A = B + C\n  B = D\n    D\n  C\nB = E\n";
+    assert!(matches!(flat::parse(FlatFormat::Outline, redefined), Err(ModelError::InvalidImport { line: Some(5), .. })));
+}
+
+#[test]
+fn children_without_algorithm_are_rejected() {
+    // 缺少parent_algorithm列、算法留空，以及大纲中有子行但没有" = "
+    let no_algorithm_column = "parent,child,position\nA,B,1\nA,C,2\n";
+    assert!(matches!(flat::parse(FlatFormat::Csv, no_algorithm_column), Err(ModelError::InvalidImport { line: Some(2), .. })));
+    let blank_algorithm = "parent,child,position,parent_algorithm\nA,B,1,B\nB,D,1,\n";
+    let error = flat::parse(FlatFormat::Csv, blank_algorithm).unwrap_err();
+    assert!(matches!(error, ModelError::InvalidImport { line: Some(3), .. }));
+    assert!(error.message().contains("模型B有子节点但没有算法"));
+    let outline = "A = B + C\n  B\n    D\n  C\n";
+    assert!(matches!(flat::parse(FlatFormat::Outline, outline), Err(ModelError::InvalidImport { line: Some(2), .. })));
+}
+
+#[test]
+fn format_from_extension() {
+    assert_eq!(FlatFormat::from_path("hierarchy.CSV"), Some(FlatFormat::Csv));
+    assert_eq!(FlatFormat::from_path("outline.txt"), Some(FlatFormat::Outline));
+    assert_eq!(FlatFormat::from_path("model.json"), None);
+}
@@ -0,0 +1,533 @@
+// ModelGraph的单元测试，模型来自models_test：A → [B, C]，B → [D]，C → [E]
+use app::diagram::DiagramFormat;
+use app::error::ModelError;
+use app::flat::{self, FlatFormat};
+use app::journal::JournalEntry;
+use app::legacy;
+use app::schema::FileModel;
+use app::{find_by_name, MergeStrategy, ModelGraph, ModelId};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+fn models_test_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../models_test")
+}
+
+fn load_test_model() -> ModelGraph {
+    let path = models_test_dir().join("test_model.json");
+    ModelGraph::load(path.to_str().unwrap(), None).unwrap()
+}
+
+// 每个测试使用单独的临时目录，测试并行运行时互不影响
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("model_graph_test_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn id_of(graph: &ModelGraph, name: &str) -> ModelId {
+    match find_by_name(name, graph.models()).as_slice() {
+        [id] => *id,
+        ids => panic!("名为{}的模型有{}个", name, ids.len()),
+    }
+}
+
+fn children_names(graph: &ModelGraph, name: &str) -> Vec<String> {
+    let model = &graph.models()[&id_of(graph, name)];
+    model.children.iter().flatten().map(|child| graph.models()[child].name.clone()).collect()
+}
+
+fn ref_count(graph: &ModelGraph, name: &str) -> u64 {
+    graph.models()[&id_of(graph, name)].ref_count
+}
+
+#[test]
+fn load_test_model_infers_root_and_adds_leaves() {
+    let graph = load_test_model();
+    assert_eq!(graph.models().len(), 5);
+    assert_eq!(graph.root_id(), id_of(&graph, "A"));
+    assert_eq!(children_names(&graph, "A"), ["B", "C"]);
+    assert_eq!(children_names(&graph, "B"), ["D"]);
+    // 只出现在children中的模型是叶节点
+    let leaf = &graph.models()[&id_of(&graph, "D")];
+    assert!(leaf.children.is_none() && leaf.algorithm.is_none());
+    assert_eq!(ref_count(&graph, "A"), 0);
+    assert_eq!(ref_count(&graph, "B"), 1);
+    assert_eq!(ref_count(&graph, "E"), 1);
+    assert_eq!(graph.parents(id_of(&graph, "D")), [id_of(&graph, "B")]);
+    assert!(graph.unique_names());
+    assert_eq!(graph.next_id(), 6);
+}
+
+#[test]
+fn load_legacy_dir_matches_model_file() {
+    let dir = temp_dir("legacy");
+    for name in ["A.json", "B.json", "C.json"] {
+        fs::copy(models_test_dir().join(name), dir.join(name)).unwrap();
+    }
+    let legacy = ModelGraph::load(dir.to_str().unwrap(), None).unwrap();
+    assert_eq!(legacy.models(), load_test_model().models());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn migrate_legacy_dir_writes_model_file() {
+    let dir = temp_dir("migrate");
+    for name in ["A.json", "B.json", "C.json"] {
+        fs::copy(models_test_dir().join(name), dir.join(name)).unwrap();
+    }
+    let path = dir.join("model.json");
+    let report = legacy::migrate(dir.to_str().unwrap(), path.to_str().unwrap()).unwrap();
+    assert!(report.written && report.diagnostics.is_empty());
+    assert_eq!(report.model_count, 3);
+    let migrated = ModelGraph::load(path.to_str().unwrap(), None).unwrap();
+    assert_eq!(migrated.models(), load_test_model().models());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn load_with_unknown_root_fails() {
+    let path = models_test_dir().join("test_model.json");
+    assert!(matches!(ModelGraph::load(path.to_str().unwrap(), Some("F")), Err(ModelError::InvalidRoot { .. })));
+    let graph = ModelGraph::load(path.to_str().unwrap(), Some("B")).unwrap();
+    assert_eq!(graph.root_id(), id_of(&graph, "B"));
+}
+
+#[test]
+fn load_reports_data_errors_by_kind() {
+    let dir = temp_dir("load_errors");
+    let load = |name: &str, content: &str| {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        ModelGraph::load(path.to_str().unwrap(), None)
+    };
+    let cycle = r#"[{"name": "A", "children": ["B"], "algorithm": "B"}, {"name": "B", "children": ["A"], "algorithm": "A"}]"#;
+    assert!(matches!(load("cycle.json", cycle), Err(ModelError::CycleDetected { .. })));
+    let missing_child = r#"{"version": 2, "next_id": 2, "unique_names": true, "models": [{"id": 1, "name": "A", "children": [5], "algorithm": "x"}]}"#;
+    assert!(matches!(load("missing.json", missing_child), Err(ModelError::NotFound { id: 5, .. })));
+    let two_roots = r#"[{"name": "A", "children": ["C"], "algorithm": "C"}, {"name": "B", "children": ["C"], "algorithm": "C"}]"#;
+    assert!(matches!(load("roots.json", two_roots), Err(ModelError::InvalidRoot { ref ids, .. }) if ids.len() == 2));
+    assert!(matches!(load("syntax.json", "[\n{\"name\": }\n]"), Err(ModelError::InvalidImport { line: Some(2), .. })));
+    assert!(matches!(ModelGraph::load(dir.join("absent.json").to_str().unwrap(), None), Err(ModelError::Io { .. })));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn save_and_reload_round_trip() {
+    let mut graph = load_test_model();
+    let new_id = graph.next_id();
+    graph.add_node(id_of(&graph, "C"), Some(0), new_id, "F").unwrap();
+    let dir = temp_dir("save");
+    let path = dir.join("model.json");
+    graph.save(path.to_str().unwrap()).unwrap();
+    let reloaded = ModelGraph::load(path.to_str().unwrap(), None).unwrap();
+    assert_eq!(reloaded.models(), graph.models());
+    assert_eq!(reloaded.next_id(), graph.next_id());
+    assert_eq!(reloaded.root_id(), graph.root_id());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn save_clears_history_so_journal_replay_matches() {
+    // 保存后日志被清空，崩溃恢复时从保存的文件重放之后的记录
+    let mut graph = load_test_model();
+    let id = id_of(&graph, "B");
+    graph.rename(id, "B2").unwrap();
+    let dir = temp_dir("save_history");
+    let path = dir.join("model.json");
+    graph.save(path.to_str().unwrap()).unwrap();
+    assert!(!graph.can_undo());
+    assert!(graph.apply_journal_entry(&JournalEntry::Undo).is_err());
+    let entries = [JournalEntry::UpdateNodeName { id, new_name: "B3".to_string() }, JournalEntry::Undo];
+    entries.iter().for_each(|entry| graph.apply_journal_entry(entry).unwrap());
+    let mut replayed = ModelGraph::load(path.to_str().unwrap(), None).unwrap();
+    entries.iter().for_each(|entry| replayed.apply_journal_entry(entry).unwrap());
+    assert_eq!(replayed.models(), graph.models());
+    assert_eq!(graph.models()[&id].name, "B2");
+    assert!(matches!(replayed.apply_journal_entry(&JournalEntry::Undo), Err(ModelError::EmptyHistory { .. })));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn modified_until_saved() {
+    let mut graph = load_test_model();
+    assert!(!graph.is_modified());
+    // 失败的修改已恢复，不算作修改
+    assert!(graph.rename(100, "X").is_err());
+    assert!(!graph.is_modified());
+    graph.rename(id_of(&graph, "B"), "B2").unwrap();
+    assert!(graph.is_modified());
+    let dir = temp_dir("modified");
+    graph.save(dir.join("model.json").to_str().unwrap()).unwrap();
+    assert!(!graph.is_modified());
+    graph.set_unique_names(!graph.unique_names()).unwrap();
+    assert!(graph.is_modified());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rename_without_duplicate_can_be_undone() {
+    let mut graph = load_test_model();
+    let original = graph.models().clone();
+    let id = id_of(&graph, "B");
+    let response = graph.rename(id, "B2").unwrap();
+    assert_eq!((response.id, response.new_name.as_str()), (id, "B2"));
+    assert_eq!(children_names(&graph, "A"), ["B2", "C"]);
+    assert!(graph.undo().is_some());
+    assert_eq!(graph.models(), &original);
+    assert!(graph.redo().is_some());
+    assert_eq!(graph.models()[&id].name, "B2");
+}
+
+#[test]
+fn rename_leaf_to_existing_name_merges() {
+    let mut graph = load_test_model();
+    let d = id_of(&graph, "D");
+    let e = id_of(&graph, "E");
+    let response = graph.rename(d, "E").unwrap();
+    assert_eq!(response.id, e);
+    assert!(!graph.models().contains_key(&d));
+    assert_eq!(children_names(&graph, "B"), ["E"]);
+    assert_eq!(ref_count(&graph, "E"), 2);
+    assert_eq!(graph.parents(e), [id_of(&graph, "B"), id_of(&graph, "C")]);
+}
+
+#[test]
+fn rename_composite_to_existing_name_adds_suffix() {
+    let mut graph = load_test_model();
+    let b = id_of(&graph, "B");
+    let response = graph.rename(b, "C").unwrap();
+    assert_eq!(response.id, b);
+    assert_ne!(response.new_name, "C");
+    assert_eq!(graph.models()[&b].name, response.new_name);
+    assert_eq!(graph.models().len(), 5);
+}
+
+#[test]
+fn rename_unknown_model_fails() {
+    let mut graph = load_test_model();
+    assert!(matches!(graph.rename(100, "F"), Err(ModelError::NotFound { id: 100, .. })));
+}
+
+#[test]
+fn add_node_inserts_leaf_and_never_reuses_id() {
+    let mut graph = load_test_model();
+    let a = id_of(&graph, "A");
+    let new_id = graph.next_id();
+    graph.add_node(a, Some(1), new_id, "F").unwrap();
+    assert_eq!(children_names(&graph, "A"), ["B", "F", "C"]);
+    assert_eq!(ref_count(&graph, "F"), 1);
+    graph.undo();
+    assert!(!graph.models().contains_key(&new_id));
+    assert_eq!(graph.next_id(), new_id + 1);
+    // 越界的位置不修改模型
+    assert!(graph.add_node(a, Some(5), graph.next_id(), "G").is_err());
+    assert_eq!(children_names(&graph, "A"), ["B", "C"]);
+}
+
+#[test]
+fn add_node_to_leaf_fails() {
+    let mut graph = load_test_model();
+    let d = id_of(&graph, "D");
+    let new_id = graph.next_id();
+    assert!(matches!(graph.add_node(d, None, new_id, "F"), Err(ModelError::NotAComposite { .. })));
+    assert!(!graph.models().contains_key(&new_id));
+}
+
+#[test]
+fn delete_node_removes_orphaned_subtree() {
+    let mut graph = load_test_model();
+    let original = graph.models().clone();
+    let a = id_of(&graph, "A");
+    let b = id_of(&graph, "B");
+    let preview = graph.preview_delete_node(a, b).unwrap();
+    assert_eq!(preview.iter().map(|node| node.name.as_str()).collect::<Vec<&str>>(), ["B", "D"]);
+    graph.delete_node(a, b).unwrap();
+    assert_eq!(children_names(&graph, "A"), ["C"]);
+    assert_eq!(graph.models().len(), 3);
+    graph.undo();
+    assert_eq!(graph.models(), &original);
+}
+
+#[test]
+fn delete_shared_node_keeps_it() {
+    let mut graph = load_test_model();
+    let b = id_of(&graph, "B");
+    let c = id_of(&graph, "C");
+    let d = id_of(&graph, "D");
+    graph.link_existing_node(c, d).unwrap();
+    assert_eq!(ref_count(&graph, "D"), 2);
+    graph.delete_node(b, d).unwrap();
+    assert_eq!(ref_count(&graph, "D"), 1);
+    assert_eq!(children_names(&graph, "C"), ["E", "D"]);
+}
+
+#[test]
+fn link_existing_node_rejects_cycle() {
+    let mut graph = load_test_model();
+    let original = graph.models().clone();
+    let a = id_of(&graph, "A");
+    let b = id_of(&graph, "B");
+    assert!(matches!(graph.link_existing_node(b, a), Err(ModelError::CycleDetected { .. })));
+    assert_eq!(graph.models(), &original);
+    // 失败的修改不进入撤销记录
+    assert!(graph.undo().is_none());
+}
+
+#[test]
+fn move_node_between_parents() {
+    let mut graph = load_test_model();
+    let b = id_of(&graph, "B");
+    let c = id_of(&graph, "C");
+    let d = id_of(&graph, "D");
+    graph.move_node(b, c, d).unwrap();
+    assert!(children_names(&graph, "B").is_empty());
+    assert_eq!(children_names(&graph, "C"), ["E", "D"]);
+    assert_eq!(graph.parents(d), [c]);
+    assert!(graph.move_node(c, d, b).is_err());
+}
+
+#[test]
+fn move_child_reorders_children() {
+    let mut graph = load_test_model();
+    let a = id_of(&graph, "A");
+    let c = id_of(&graph, "C");
+    graph.move_child(a, c, 0).unwrap();
+    assert_eq!(children_names(&graph, "A"), ["C", "B"]);
+    assert!(graph.move_child(a, c, 2).is_err());
+}
+
+#[test]
+fn deep_copy_subtree_copies_descendants() {
+    let mut graph = load_test_model();
+    let a = id_of(&graph, "A");
+    let b = id_of(&graph, "B");
+    let new_id = graph.next_id();
+    graph.deep_copy_subtree(b, a, new_id).unwrap();
+    assert_eq!(graph.models().len(), 7);
+    assert_eq!(graph.next_id(), new_id + 2);
+    let copy = &graph.models()[&new_id];
+    assert_ne!(copy.name, "B");
+    assert_eq!(graph.models()[&a].children.as_ref().unwrap().last(), Some(&new_id));
+    // 副本的子节点是D的副本，原来的D仍只被B引用
+    let child = copy.children.as_ref().unwrap()[0];
+    assert_ne!(child, id_of(&graph, "D"));
+    assert_eq!(ref_count(&graph, "D"), 1);
+}
+
+#[test]
+fn deep_copy_subtree_renames_all_references() {
+    let composite = |name: &str, children: &[&str], algorithm: &str| FileModel {
+        name: name.to_string(),
+        children: Some(children.iter().map(|child| child.to_string()).collect()),
+        algorithm: Some(algorithm.to_string()),
+        pinned: false,
+    };
+    let mut graph = ModelGraph::from_file_models(vec![
+        composite("R", &["X"], "X"),
+        composite("X", &["P", "P（副本）"], "P * 2 + \"P（副本）\""),
+    ]).unwrap();
+    let (r, x) = (id_of(&graph, "R"), id_of(&graph, "X"));
+    let new_id = graph.next_id();
+    graph.deep_copy_subtree(x, r, new_id).unwrap();
+    let copy = &graph.models()[&new_id];
+    assert_eq!(copy.name, "X（副本）");
+    assert_eq!(copy.algorithm.as_deref(), Some("P（副本2） * 2 + P（副本）（副本）"));
+}
+
+#[test]
+fn merge_nodes_union_relinks_parents() {
+    let mut graph = load_test_model();
+    let b = id_of(&graph, "B");
+    let c = id_of(&graph, "C");
+    let report = graph.merge_nodes(b, c, MergeStrategy::Union).unwrap();
+    assert!(!graph.models().contains_key(&b));
+    assert_eq!(children_names(&graph, "A"), ["C"]);
+    assert_eq!(children_names(&graph, "C"), ["E", "D"]);
+    assert_eq!(report.added_children.iter().map(|node| node.name.as_str()).collect::<Vec<&str>>(), ["D"]);
+    assert!(report.removed.is_empty());
+    assert!(graph.merge_nodes(c, c, MergeStrategy::Union).is_err());
+}
+
+#[test]
+fn merge_nodes_replace_removes_orphans() {
+    let mut graph = load_test_model();
+    let b = id_of(&graph, "B");
+    let c = id_of(&graph, "C");
+    let report = graph.merge_nodes(b, c, MergeStrategy::Replace).unwrap();
+    assert_eq!(children_names(&graph, "C"), ["D"]);
+    assert_eq!(report.removed.iter().map(|node| node.name.as_str()).collect::<Vec<&str>>(), ["E"]);
+    assert_eq!(report.algorithm.as_deref(), Some("算法B"));
+}
+
+#[test]
+fn batch_rename_reports_collisions() {
+    let mut graph = load_test_model();
+    let report = graph.batch_rename("^[DE]$", "X").unwrap();
+    assert!(!report.applied);
+    assert_eq!(report.collisions.len(), 1);
+    assert_eq!(graph.models().len(), 5);
+    let report = graph.batch_rename("^([DE])$", "${1}1").unwrap();
+    assert!(report.applied);
+    assert_eq!(children_names(&graph, "B"), ["D1"]);
+    assert_eq!(children_names(&graph, "C"), ["E1"]);
+}
+
+#[test]
+fn update_algorithm_validates_references() {
+    let mut graph = load_test_model();
+    let b = id_of(&graph, "B");
+    assert!(matches!(graph.update_algorithm(b, "E * 2"), Err(ModelError::InvalidAlgorithm { .. })));
+    assert_eq!(graph.models()[&b].algorithm.as_deref(), Some("算法B"));
+    graph.update_algorithm(b, "D * 2").unwrap();
+    let leaf_values = HashMap::from([(id_of(&graph, "D"), 3.0)]);
+    let evaluation = graph.evaluate(&leaf_values, Some(b)).unwrap();
+    assert_eq!(evaluation.root_score, 6.0);
+    let values = HashMap::from([("D".to_string(), 4.0)]);
+    assert_eq!(graph.evaluate_algorithm(b, &values).unwrap(), 8.0);
+}
+
+#[test]
+fn toggle_has_children_round_trip() {
+    let mut graph = load_test_model();
+    let d = id_of(&graph, "D");
+    graph.toggle_has_children(d).unwrap();
+    assert_eq!(graph.models()[&d].children, Some(vec![]));
+    assert!(graph.models()[&d].algorithm.is_some());
+    let b = id_of(&graph, "B");
+    let preview = graph.preview_toggle_has_children(b).unwrap();
+    assert_eq!(preview.iter().map(|node| node.id).collect::<Vec<ModelId>>(), [d]);
+    graph.toggle_has_children(b).unwrap();
+    // 去掉子项后不再被引用的子节点一并删除，不会成为新的根节点
+    assert!(!graph.models().contains_key(&d));
+    assert_eq!(graph.roots().iter().map(|node| node.name.as_str()).collect::<Vec<&str>>(), ["A"]);
+    graph.undo();
+    assert_eq!(ref_count(&graph, "D"), 1);
+    assert_eq!(graph.models()[&d].children, Some(vec![]));
+}
+
+#[test]
+fn toggle_to_leaf_keeps_shared_children() {
+    let mut graph = load_test_model();
+    let (b, c, e) = (id_of(&graph, "B"), id_of(&graph, "C"), id_of(&graph, "E"));
+    graph.link_existing_node(b, e).unwrap();
+    assert_eq!(graph.preview_toggle_has_children(b).unwrap().iter().map(|node| node.name.as_str()).collect::<Vec<&str>>(), ["D"]);
+    graph.toggle_has_children(b).unwrap();
+    assert_eq!(graph.models().len(), 4);
+    assert_eq!(graph.parents(e), [c]);
+    assert!(graph.preview_toggle_has_children(b).unwrap().is_empty());
+}
+
+#[test]
+fn set_unique_names_rejects_existing_duplicates() {
+    let mut graph = load_test_model();
+    graph.set_unique_names(false).unwrap();
+    let d = id_of(&graph, "D");
+    graph.rename(d, "E").unwrap();
+    assert_eq!(find_by_name("E", graph.models()).len(), 2);
+    assert!(matches!(graph.set_unique_names(true), Err(ModelError::NameConflict { .. })));
+    assert!(!graph.unique_names());
+}
+
+#[test]
+fn take_events_reports_changes_once() {
+    let mut graph = load_test_model();
+    let b = id_of(&graph, "B");
+    graph.rename(b, "B2").unwrap();
+    let events = graph.take_events();
+    assert_eq!(events.renamed.len(), 1);
+    assert!(graph.take_events().renamed.is_empty());
+}
+
+#[test]
+fn pinning_reports_pinned_changed() {
+    let mut graph = load_test_model();
+    let b = id_of(&graph, "B");
+    graph.set_pinned(b, true).unwrap();
+    let pinned = |graph: &mut ModelGraph| graph.take_events().pinned_changed.iter().map(|model| (model.id, model.pinned)).collect::<Vec<_>>();
+    assert_eq!(pinned(&mut graph), [(b, true)]);
+    graph.undo();
+    assert_eq!(pinned(&mut graph), [(b, false)]);
+    graph.set_unique_names(false).unwrap();
+    assert_eq!(graph.take_events(), Default::default());
+}
+
+#[test]
+fn export_graph_draws_shared_nodes_once() {
+    let mut graph = load_test_model();
+    let c = id_of(&graph, "C");
+    let d = id_of(&graph, "D");
+    graph.link_existing_node(c, d).unwrap();
+    let dot = graph.export_graph(DiagramFormat::Dot, None, None).unwrap();
+    assert_eq!(dot.matches(&format!("n{} [", d)).count(), 1);
+    assert!(dot.contains(&format!("n{} [label=\"D\", peripheries=2];", d)));
+    assert_eq!(dot.matches(&format!("-> n{};", d)).count(), 2);
+    assert!(dot.contains("label=\"A\\n算法A\""));
+    let mermaid = graph.export_graph(DiagramFormat::Mermaid, None, None).unwrap();
+    assert!(mermaid.starts_with("flowchart TD\n"));
+    assert!(mermaid.contains(&format!("n{}[\"B<br/>算法B\"]", id_of(&graph, "B"))));
+    assert!(mermaid.contains(&format!("class n{} shared", d)));
+}
+
+#[test]
+fn export_graph_limits_depth() {
+    let graph = load_test_model();
+    let b = id_of(&graph, "B");
+    let mermaid = graph.export_graph(DiagramFormat::Mermaid, None, Some(1)).unwrap();
+    assert!(!mermaid.contains(&format!("n{}[", id_of(&graph, "D"))));
+    assert!(mermaid.contains(&format!("class n{},n{} truncated", b, id_of(&graph, "C"))));
+    let dot = graph.export_graph(DiagramFormat::Dot, Some(b), Some(0)).unwrap();
+    assert!(dot.starts_with("digraph \"B\" {"));
+    assert!(!dot.contains("->"));
+    assert!(graph.export_graph(DiagramFormat::Dot, Some(100), None).is_err());
+}
+
+#[test]
+fn import_reports_diff_and_applies_as_one_edit() {
+    let mut graph = load_test_model();
+    let original = graph.models().clone();
+    let b = id_of(&graph, "B");
+    let content = "A = B\n  B = D * 2\n    D\n    F\n";
+    let file_models = flat::parse(FlatFormat::Outline, content).unwrap();
+    let report = graph.plan_import(file_models.clone()).unwrap();
+    assert!(!report.applied);
+    assert_eq!(report.added, ["F"]);
+    assert_eq!(report.removed, ["C", "E"]);
+    assert_eq!(report.changed.iter().map(|diff| diff.name.as_str()).collect::<Vec<&str>>(), ["A", "B"]);
+    assert_eq!(graph.models(), &original);
+    let report = graph.import(file_models).unwrap();
+    assert!(report.applied);
+    // 同名的模型保留原来的ID，新模型使用新的ID
+    assert_eq!(id_of(&graph, "B"), b);
+    assert_eq!(id_of(&graph, "F"), 6);
+    assert_eq!(graph.next_id(), 7);
+    assert_eq!(children_names(&graph, "B"), ["D", "F"]);
+    assert_eq!(ref_count(&graph, "F"), 1);
+    assert!(graph.undo().is_some());
+    assert_eq!(graph.models(), &original);
+}
+
+#[test]
+fn import_rejects_children_without_algorithm() {
+    let mut graph = load_test_model();
+    let original = graph.models().clone();
+    let mut file_models = flat::parse(FlatFormat::Outline, "A = B\n  B = D\n    D\n").unwrap();
+    file_models.iter_mut().filter(|model| model.name == "B").for_each(|model| model.algorithm = None);
+    assert!(matches!(graph.plan_import(file_models.clone()), Err(ModelError::InvalidImport { line: None, .. })));
+    assert!(matches!(graph.import(file_models), Err(ModelError::InvalidImport { .. })));
+    assert_eq!(graph.models(), &original);
+}
+
+#[test]
+fn export_then_import_is_unchanged() {
+    let mut graph = load_test_model();
+    let c = id_of(&graph, "C");
+    graph.link_existing_node(c, id_of(&graph, "D")).unwrap();
+    for format in [FlatFormat::Csv, FlatFormat::Outline] {
+        let content = flat::render(format, &graph.file_models().unwrap()).unwrap();
+        let report = graph.import(flat::parse(format, &content).unwrap()).unwrap();
+        assert!(report.is_empty() && !report.applied);
+    }
+}
@@ -0,0 +1,172 @@
+// ModelGraph的性质测试：对models_test中的模型执行随机的修改序列，检查每一步后模型图仍然一致，
+// 并且全部撤销后回到初始状态、全部重做后回到修改后的状态
+use app::journal::JournalEntry;
+use app::{graph, MergeStrategy, ModelGraph, ModelId};
+use proptest::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+// 重命名时从中选取，包含已有的名称以覆盖重名合并和加后缀
+const NAMES: &[&str] = &["A", "B", "D", "E", "F", "G"];
+
+// 模型以其在按ID排序的模型列表中的位置指定，执行时对模型数取余，使生成的操作总能落到已有的模型上
+#[derive(Debug, Clone)]
+enum Op {
+    Rename(usize, usize),
+    AddNode(usize, Option<usize>),
+    DeleteNode(usize, usize),
+    LinkExistingNode(usize, usize),
+    MoveNode(usize, usize, usize),
+    MoveChild(usize, usize, usize),
+    DeepCopySubtree(usize, usize),
+    MergeNodes(usize, usize, bool),
+    ToggleHasChildren(usize),
+    SetPinned(usize, bool),
+    Undo,
+    Redo,
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let index = || 0..16usize;
+    prop_oneof![
+        (index(), 0..NAMES.len()).prop_map(|(id, name)| Op::Rename(id, name)),
+        (index(), proptest::option::of(0..4usize)).prop_map(|(parent, position)| Op::AddNode(parent, position)),
+        (index(), index()).prop_map(|(parent, child)| Op::DeleteNode(parent, child)),
+        (index(), index()).prop_map(|(parent, id)| Op::LinkExistingNode(parent, id)),
+        (index(), index(), index()).prop_map(|(old_parent, child, new_parent)| Op::MoveNode(old_parent, child, new_parent)),
+        (index(), index(), 0..4usize).prop_map(|(parent, child, new_index)| Op::MoveChild(parent, child, new_index)),
+        (index(), index()).prop_map(|(id, parent)| Op::DeepCopySubtree(id, parent)),
+        (index(), index(), any::<bool>()).prop_map(|(source, target, union)| Op::MergeNodes(source, target, union)),
+        index().prop_map(Op::ToggleHasChildren),
+        (index(), any::<bool>()).prop_map(|(id, pinned)| Op::SetPinned(id, pinned)),
+        Just(Op::Undo),
+        Just(Op::Redo),
+    ]
+}
+
+fn load_test_model() -> ModelGraph {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../models_test/test_model.json");
+    ModelGraph::load(path.to_str().unwrap(), None).unwrap()
+}
+
+fn nth_id(graph: &ModelGraph, index: usize) -> ModelId {
+    let mut ids = graph.models().keys().copied().collect::<Vec<ModelId>>();
+    ids.sort_unstable();
+    ids[index % ids.len()]
+}
+
+// 父节点的第index个子节点，没有子节点时为None
+fn nth_child(graph: &ModelGraph, parent_id: ModelId, index: usize) -> Option<ModelId> {
+    let children = graph.models()[&parent_id].children.as_ref()?;
+    children.get(index % children.len().max(1)).copied()
+}
+
+// 将操作落到当前的模型上，转为日志记录；父节点没有子节点等无法构成记录时为None
+fn entry(graph: &ModelGraph, op: &Op) -> Option<JournalEntry> {
+    let entry = match *op {
+        Op::Rename(id, name) => JournalEntry::UpdateNodeName { id: nth_id(graph, id), new_name: NAMES[name].to_string() },
+        Op::AddNode(parent, position) => {
+            let (parent_id, new_id, new_name) = (nth_id(graph, parent), graph.next_id(), graph.new_node_name());
+            match position {
+                Some(index) => JournalEntry::InsertNodeAt { parent_id, index, new_id, new_name },
+                None => JournalEntry::AddNode { parent_id, new_id, new_name },
+            }
+        }
+        Op::DeleteNode(parent, child) => {
+            let parent_id = nth_id(graph, parent);
+            JournalEntry::DeleteNode { parent_id, id: nth_child(graph, parent_id, child)? }
+        }
+        Op::LinkExistingNode(parent, id) => JournalEntry::LinkExistingNode { parent_id: nth_id(graph, parent), id: nth_id(graph, id) },
+        Op::MoveNode(old_parent, child, new_parent) => {
+            let old_parent_id = nth_id(graph, old_parent);
+            let id = nth_child(graph, old_parent_id, child)?;
+            JournalEntry::MoveNode { old_parent_id, new_parent_id: nth_id(graph, new_parent), id }
+        }
+        Op::MoveChild(parent, child, new_index) => {
+            let parent_id = nth_id(graph, parent);
+            JournalEntry::MoveChild { parent_id, id: nth_child(graph, parent_id, child)?, new_index }
+        }
+        Op::DeepCopySubtree(id, parent) => JournalEntry::DeepCopySubtree { id: nth_id(graph, id), new_parent_id: nth_id(graph, parent), new_id: graph.next_id() },
+        Op::MergeNodes(source, target, union) => {
+            let strategy = if union { MergeStrategy::Union } else { MergeStrategy::Replace };
+            JournalEntry::MergeNodes { source_id: nth_id(graph, source), target_id: nth_id(graph, target), strategy }
+        }
+        Op::ToggleHasChildren(id) => JournalEntry::ToggleHasChildren { id: nth_id(graph, id) },
+        Op::SetPinned(id, true) => JournalEntry::AddRoot { id: nth_id(graph, id) },
+        Op::SetPinned(id, false) => JournalEntry::RemoveRoot { id: nth_id(graph, id) },
+        Op::Undo => JournalEntry::Undo,
+        Op::Redo => JournalEntry::Redo,
+    };
+    Some(entry)
+}
+
+fn check_consistent(graph: &ModelGraph) -> Result<(), TestCaseError> {
+    let models = graph.models();
+    let mut references: HashMap<ModelId, BTreeMap<ModelId, u64>> = HashMap::new();
+    for model in models.values() {
+        prop_assert_eq!(model.children.is_some(), model.algorithm.is_some(), "模型{}的子节点与算法不一致", model.name);
+        for child in model.children.iter().flatten() {
+            prop_assert!(models.contains_key(child), "模型{}的子节点{}不存在", model.name, child);
+            *references.entry(*child).or_default().entry(model.id).or_default() += 1;
+        }
+    }
+    for model in models.values() {
+        let parents = references.remove(&model.id).unwrap_or_default();
+        prop_assert_eq!(model.ref_count, parents.values().sum::<u64>(), "模型{}的引用计数错误", model.name);
+        prop_assert_eq!(graph.parents(model.id), parents.keys().copied().collect::<Vec<ModelId>>(), "模型{}的父节点错误", model.name);
+    }
+    prop_assert!(graph::validate_graph(models).is_empty(), "模型图中有环");
+    prop_assert!(models.contains_key(&graph.root_id()), "根节点不存在");
+    prop_assert!(models.keys().all(|id| *id < graph.next_id()), "next_id小于已有的ID");
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn random_edits_keep_graph_consistent(ops in proptest::collection::vec(op(), 1..40)) {
+        let mut graph = load_test_model();
+        let original = graph.models().clone();
+        for op in &ops {
+            let entry = match entry(&graph, op) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let before = graph.clone();
+            // 修改失败是允许的，但失败时模型不应改变
+            if graph.apply_journal_entry(&entry).is_err() {
+                prop_assert_eq!(graph.models(), before.models(), "失败的修改{:?}改变了模型", entry);
+            }
+            check_consistent(&graph)?;
+        }
+        // 重做剩余的修改后，全部撤销应回到初始状态，全部重做应回到撤销前的状态
+        while graph.redo().is_some() {}
+        let edited = graph.models().clone();
+        while graph.undo().is_some() {
+            check_consistent(&graph)?;
+        }
+        prop_assert_eq!(graph.models(), &original);
+        while graph.redo().is_some() {}
+        prop_assert_eq!(graph.models(), &edited);
+    }
+
+    #[test]
+    fn journal_replay_reproduces_edits(ops in proptest::collection::vec(op(), 1..40)) {
+        // 崩溃恢复时从保存的模型重放日志，应得到与崩溃前相同的模型，包括执行失败的记录
+        let mut graph = load_test_model();
+        let mut entries = Vec::new();
+        for op in &ops {
+            if let Some(entry) = entry(&graph, op) {
+                let _ = graph.apply_journal_entry(&entry);
+                entries.push(entry);
+            }
+        }
+        let mut replayed = load_test_model();
+        for entry in &entries {
+            let _ = replayed.apply_journal_entry(entry);
+        }
+        prop_assert_eq!(replayed.models(), graph.models());
+        prop_assert_eq!(replayed.next_id(), graph.next_id());
+    }
+}